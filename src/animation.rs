@@ -0,0 +1,542 @@
+//! # Animation sampling
+//! Evaluation of the curves of an `AnmEntry` at any frame.
+//!
+//! Frames are counted in whole frames. Linear and bezier keyframes store their frame multiplied
+//! by the anm's frame size (100), and table formats store one value per frame, starting at frame 0.
+//! Samples before the first keyframe or after the last one hold the nearest keyframe.
+//!
+//! Frames are first mapped into the animation's length, wrapping around for looped animations and
+//! clamping otherwise, so an entry samples the same whether it's evaluated alone or through its anm.
+//!
+//! The in and out tangents of bezier keyframes are taken to be absolute values, used directly as
+//! the inner control points of a cubic bezier between two keyframes. Handles relative to the value
+//! or with their own frame positions would need converting first.
+//!
+use super::nucc::NuccAnm;
+use super::nucc_chunk::nucc_chunk_anm::{AnmEntry, Curve, CurveFormat, EntryFormat, Math};
+use super::nucc_chunk::nucc_helper::{Vector3, Vector4};
+
+/// Units per frame of the frames stored in keyframes and in `NuccAnm::frame_count`
+pub const FRAME_SIZE: f32 = 100.0;
+
+/// The value of a curve at a frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum CurveValue {
+    Float(f32),
+    Vec2(f32, f32),
+    Vec3(Vector3),
+
+    /// Rotation around the X, Y and Z axes in radians, applied in that order
+    Euler(Vector3),
+
+    Quaternion(Vector4),
+
+    /// RGB color with each channel from 0 to 1
+    Color(Vector3),
+}
+
+/// The values of every curve of an entry at a frame
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EntryPose {
+    pub location: Option<Vector3>,
+
+    /// Rotation as a quaternion, euler rotations are converted
+    pub rotation: Option<Vector4>,
+
+    pub scale: Option<Vector3>,
+    pub opacity: Option<f32>,
+    pub color: Option<Vector3>,
+
+    /// Values of the curves that aren't one of the above, with their curve index
+    pub other: Vec<(u16, CurveValue)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Interpolation {
+    Step,
+    Linear,
+    Bezier,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ValueKind {
+    Float,
+    Vec2,
+    Vec3,
+    Euler,
+    Quaternion,
+    Color,
+}
+
+/// A decoded keyframe, with the bezier handles of its value when it has them
+#[derive(Debug, Clone, Copy)]
+struct Key {
+    frame: f32,
+    value: [f32; 4],
+    in_tangent: [f32; 4],
+    out_tangent: [f32; 4],
+}
+
+impl CurveFormat {
+    pub(crate) fn interpolation(&self) -> Interpolation {
+        match self {
+            CurveFormat::FloatTableNoInterp
+            | CurveFormat::Vector3TableNoInterp
+            | CurveFormat::QuaternionShortTableNoInterp
+            | CurveFormat::OpacityShortTableNoInterp => Interpolation::Step,
+            CurveFormat::Vector3Bezier => Interpolation::Bezier,
+            _ => Interpolation::Linear,
+        }
+    }
+
+    pub(crate) fn value_kind(&self) -> Option<ValueKind> {
+        Some(match self {
+            CurveFormat::FloatFixed
+            | CurveFormat::FloatLinear
+            | CurveFormat::FloatTable
+            | CurveFormat::FloatTableNoInterp
+            | CurveFormat::OpacityShortTable
+            | CurveFormat::OpacityShortTableNoInterp => ValueKind::Float,
+            CurveFormat::Vector2Fixed | CurveFormat::Vector2Linear => ValueKind::Vec2,
+            CurveFormat::Vector3Fixed
+            | CurveFormat::Vector3Linear
+            | CurveFormat::Vector3Bezier
+            | CurveFormat::Vector3Table
+            | CurveFormat::Vector3TableNoInterp
+            | CurveFormat::ScaleShortTable
+            | CurveFormat::Vector3ShortLinear => ValueKind::Vec3,
+            CurveFormat::EulerXYZFixed | CurveFormat::EulerInterpolated => ValueKind::Euler,
+            CurveFormat::QuaternionLinear
+            | CurveFormat::QuaternionShortTable
+            | CurveFormat::QuaternionShortTableNoInterp
+            | CurveFormat::QuaternionTable => ValueKind::Quaternion,
+            CurveFormat::ColorRGBTable => ValueKind::Color,
+            CurveFormat::Unknown(_) => return None,
+        })
+    }
+}
+
+impl Curve {
+    /// The value of the curve at `frame`, or `None` if the curve has no keyframes or an unknown format
+    pub fn sample(&self, frame: f32) -> Option<CurveValue> {
+        let kind = self.curve_format.value_kind()?;
+        let keys = self.keys();
+
+        let value = match keys.iter().position(|key| key.frame > frame) {
+            None => keys.last()?.value,
+            Some(0) => keys[0].value,
+            Some(next) => {
+                let (k0, k1) = (&keys[next - 1], &keys[next]);
+                let t = (frame - k0.frame) / (k1.frame - k0.frame);
+
+                match self.curve_format.interpolation() {
+                    Interpolation::Step => k0.value,
+                    Interpolation::Linear if kind == ValueKind::Quaternion => slerp(k0.value, k1.value, t),
+                    Interpolation::Linear => lerp(k0.value, k1.value, t),
+                    Interpolation::Bezier => bezier(k0.value, k0.out_tangent, k1.in_tangent, k1.value, t),
+                }
+            }
+        };
+
+        let [x, y, z, w] = value;
+
+        Some(match kind {
+            ValueKind::Float => CurveValue::Float(x),
+            ValueKind::Vec2 => CurveValue::Vec2(x, y),
+            ValueKind::Vec3 => CurveValue::Vec3(Vector3 { x, y, z }),
+            ValueKind::Euler => CurveValue::Euler(Vector3 { x, y, z }),
+            ValueKind::Quaternion => CurveValue::Quaternion(Vector4 { x, y, z, w }),
+            ValueKind::Color => CurveValue::Color(Vector3 { x, y, z }),
+        })
+    }
+
+    /// Frame of each keyframe that has a value
+    pub(crate) fn key_frames(&self) -> Vec<f32> {
+        self.keys().iter().map(|key| key.frame).collect()
+    }
+
+    /// The keyframes as floats, with table values placed at their frame
+    fn keys(&self) -> Vec<Key> {
+        self.keyframes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, keyframe)| {
+                let (frame, value, tangents) = keyframe_value(keyframe)?;
+                let (in_tangent, out_tangent) = tangents.unwrap_or((value, value));
+
+                // Quaternions are normalized so that they interpolate as rotations
+                let value = match self.curve_format.value_kind() {
+                    Some(ValueKind::Quaternion) => normalize(value),
+                    _ => value,
+                };
+
+                Some(Key {
+                    frame: frame.map_or(index as f32, |frame| frame as f32 / FRAME_SIZE),
+                    value,
+                    in_tangent,
+                    out_tangent,
+                })
+            })
+            .collect()
+    }
+}
+
+type Tangents = ([f32; 4], [f32; 4]);
+
+/// The stored frame, value and bezier handles of a keyframe
+fn keyframe_value(keyframe: &Math) -> Option<(Option<i32>, [f32; 4], Option<Tangents>)> {
+    let vec3 = |(x, y, z): (f32, f32, f32)| [x, y, z, 0.0];
+
+    Some(match *keyframe {
+        Math::Vec2 { channels: (x, y) } => (None, [x, y, 0.0, 0.0], None),
+        Math::Vec2Linear { frame, channels: (x, y) } => (Some(frame), [x, y, 0.0, 0.0], None),
+        Math::Vec3 { channels } => (None, vec3(channels), None),
+        Math::Vec3Linear { frame, channels } => (Some(frame), vec3(channels), None),
+        Math::Vec3Bezier {
+            frame,
+            channels,
+            in_tangent,
+            out_tangent,
+        } => (Some(frame), vec3(channels), Some((vec3(in_tangent), vec3(out_tangent)))),
+        Math::Vec4 { channels: (x, y, z, w) } => (None, [x, y, z, w], None),
+        Math::Vec4Linear {
+            frame,
+            channels: (x, y, z, w),
+        } => (Some(frame), [x, y, z, w], None),
+        Math::Float { channels } => (None, [channels, 0.0, 0.0, 0.0], None),
+        Math::FloatLinear { frame, channels } => (Some(frame), [channels, 0.0, 0.0, 0.0], None),
+        Math::Color { channels: (r, g, b) } => (None, [r, g, b, 0].map(|c| c as f32 / 255.0), None),
+        Math::Raw { .. } => return None,
+    })
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+fn dot(a: [f32; 4], b: [f32; 4]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn normalize(q: [f32; 4]) -> [f32; 4] {
+    let length = dot(q, q).sqrt();
+
+    if length > 0.0 {
+        q.map(|c| c / length)
+    } else {
+        [0.0, 0.0, 0.0, 1.0]
+    }
+}
+
+/// Spherical interpolation along the shortest path between two quaternions
+fn slerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut cos = dot(a, b);
+    let mut b = b;
+
+    if cos < 0.0 {
+        cos = -cos;
+        b = b.map(|c| -c);
+    }
+
+    // Nearly identical rotations would divide by a sine close to 0
+    if cos > 0.9995 {
+        return normalize(lerp(a, b, t));
+    }
+
+    let angle = cos.acos();
+    let sin = angle.sin();
+    let (wa, wb) = (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin);
+
+    [0, 1, 2, 3].map(|i| a[i] * wa + b[i] * wb)
+}
+
+/// Cubic bezier from `p0` to `p1` with the absolute handles `c0` and `c1` as control points
+fn bezier(p0: [f32; 4], c0: [f32; 4], c1: [f32; 4], p1: [f32; 4], t: f32) -> [f32; 4] {
+    let u = 1.0 - t;
+
+    [0, 1, 2, 3].map(|i| u * u * u * p0[i] + 3.0 * u * u * t * c0[i] + 3.0 * u * t * t * c1[i] + t * t * t * p1[i])
+}
+
+/// Quaternion of a rotation around X, then Y, then Z
+pub(crate) fn euler_to_quaternion(euler: &Vector3) -> Vector4 {
+    let (sx, cx) = (euler.x * 0.5).sin_cos();
+    let (sy, cy) = (euler.y * 0.5).sin_cos();
+    let (sz, cz) = (euler.z * 0.5).sin_cos();
+
+    Vector4 {
+        x: sx * cy * cz - cx * sy * sz,
+        y: cx * sy * cz + sx * cy * sz,
+        z: cx * cy * sz - sx * sy * cz,
+        w: cx * cy * cz + sx * sy * sz,
+    }
+}
+
+/// Which pose field a curve of an entry animates
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Track {
+    Location,
+    Rotation,
+    Scale,
+    Opacity,
+    Color,
+    Other,
+}
+
+fn track(entry_format: &EntryFormat, curve_index: u16) -> Track {
+    match (entry_format, curve_index) {
+        (EntryFormat::Coord, 0) | (EntryFormat::Camera, 0) | (EntryFormat::LightPoint, 1) => Track::Location,
+        (EntryFormat::Coord, 1) | (EntryFormat::Camera, 1) | (EntryFormat::LightDirc, 2) => Track::Rotation,
+        (EntryFormat::Coord, 2) => Track::Scale,
+        (EntryFormat::Coord, 3) => Track::Opacity,
+        (EntryFormat::LightDirc, 0) | (EntryFormat::LightPoint, 0) | (EntryFormat::Ambient, 0) => Track::Color,
+        _ => Track::Other,
+    }
+}
+
+/// Maps `frame` into an animation `length` frames long, wrapping around if it's looped and clamping otherwise
+pub fn wrap_frame(frame: f32, length: f32, is_looped: bool) -> f32 {
+    if length <= 0.0 {
+        0.0
+    } else if is_looped {
+        frame.rem_euclid(length)
+    } else {
+        frame.clamp(0.0, length)
+    }
+}
+
+impl AnmEntry {
+    /// Evaluates every curve of the entry at `frame` of an animation `length` frames long
+    ///
+    /// The frame is wrapped with `wrap_frame` first. Curves are assigned to pose fields by their
+    /// curve index and the entry format, curves that don't match the field's type or that no
+    /// field exists for are kept in `EntryPose::other`
+    pub fn sample(&self, frame: f32, length: f32, is_looped: bool) -> EntryPose {
+        let frame = wrap_frame(frame, length, is_looped);
+        let mut pose = EntryPose::default();
+
+        for (header, curve) in self.curve_headers.iter().zip(&self.curves) {
+            let value = match curve.sample(frame) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match (track(&self.entry_format, header.curve_index), value) {
+                (Track::Location, CurveValue::Vec3(location)) => pose.location = Some(location),
+                (Track::Rotation, CurveValue::Quaternion(rotation)) => pose.rotation = Some(rotation),
+                (Track::Rotation, CurveValue::Euler(euler)) => pose.rotation = Some(euler_to_quaternion(&euler)),
+                (Track::Scale, CurveValue::Vec3(scale)) => pose.scale = Some(scale),
+                (Track::Opacity, CurveValue::Float(opacity)) => pose.opacity = Some(opacity),
+                (Track::Color, CurveValue::Color(color) | CurveValue::Vec3(color)) => pose.color = Some(color),
+                (_, value) => pose.other.push((header.curve_index, value)),
+            }
+        }
+
+        pose
+    }
+}
+
+impl NuccAnm {
+    /// Length of the animation in frames
+    pub fn frame_length(&self) -> f32 {
+        self.frame_count as f32 / FRAME_SIZE
+    }
+
+    /// Maps `frame` into the animation, wrapping around if it's looped and clamping otherwise
+    pub fn wrap_frame(&self, frame: f32) -> f32 {
+        wrap_frame(frame, self.frame_length(), self.is_looped)
+    }
+
+    /// Evaluates every entry at `frame`, after wrapping it with `wrap_frame`
+    pub fn sample(&self, frame: f32) -> Vec<EntryPose> {
+        let length = self.frame_length();
+
+        self.entries
+            .iter()
+            .map(|entry| entry.sample(frame, length, self.is_looped))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn anm_sampling_test() {
+        let keys = |format: CurveFormat, keyframes: Vec<Math>| Curve {
+            curve_format: format,
+            keyframes,
+        };
+
+        let curves = vec![
+            keys(
+                CurveFormat::Vector3Linear,
+                vec![
+                    Math::Vec3Linear {
+                        frame: 0,
+                        channels: (0.0, 0.0, 0.0),
+                    },
+                    Math::Vec3Linear {
+                        frame: 200,
+                        channels: (2.0, 4.0, 6.0),
+                    },
+                ],
+            ),
+            keys(
+                CurveFormat::QuaternionLinear,
+                vec![
+                    Math::Vec4Linear {
+                        frame: 0,
+                        channels: (0.0, 0.0, 0.0, 1.0),
+                    },
+                    Math::Vec4Linear {
+                        frame: 200,
+                        channels: (0.0, 0.0, std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2),
+                    },
+                ],
+            ),
+            keys(
+                CurveFormat::Vector3Bezier,
+                vec![
+                    Math::Vec3Bezier {
+                        frame: 0,
+                        channels: (1.0, 1.0, 1.0),
+                        in_tangent: (1.0, 1.0, 1.0),
+                        out_tangent: (1.0, 1.0, 1.0),
+                    },
+                    Math::Vec3Bezier {
+                        frame: 200,
+                        channels: (3.0, 3.0, 3.0),
+                        in_tangent: (3.0, 3.0, 3.0),
+                        out_tangent: (3.0, 3.0, 3.0),
+                    },
+                ],
+            ),
+            keys(
+                CurveFormat::OpacityShortTableNoInterp,
+                [1.0, 0.5, 0.0].into_iter().map(|channels| Math::Float { channels }).collect(),
+            ),
+            keys(CurveFormat::FloatFixed, vec![Math::Float { channels: 7.0 }]),
+        ];
+
+        let curve_headers = curves
+            .iter()
+            .enumerate()
+            .map(|(i, curve)| {
+                let size = curve.curve_format.size_per_frame().unwrap() * curve.keyframes.len();
+                curve_header(i as u16, curve.curve_format.clone(), curve.keyframes.len() as u16, size as u16)
+            })
+            .collect();
+
+        let entry = AnmEntry {
+            coord: coord(0, 0),
+            entry_format: EntryFormat::Coord,
+            curve_headers,
+            curves,
+        };
+
+        let pose = entry.sample(1.0, 10.0, false);
+        let location = pose.location.unwrap();
+        let rotation = pose.rotation.unwrap();
+        let scale = pose.scale.unwrap();
+
+        assert_vec_close(&[location.x, location.y, location.z], &[1.0, 2.0, 3.0]);
+
+        // Halfway between no rotation and 90 degrees around Z
+        let half = std::f32::consts::FRAC_PI_8;
+        assert_vec_close(&[rotation.x, rotation.y, rotation.z, rotation.w], &[0.0, 0.0, half.sin(), half.cos()]);
+
+        // The handles ease in and out, so the middle of the curve is still halfway
+        assert_vec_close(&[scale.x, scale.y, scale.z], &[2.0, 2.0, 2.0]);
+        let scale = entry.sample(0.5, 10.0, false).scale.unwrap();
+        assert!(scale.x > 1.0 && scale.x < 1.5);
+
+        // Tables without interpolation hold each value until the next frame
+        assert_eq!(pose.opacity, Some(0.5));
+        assert_eq!(entry.sample(0.9, 10.0, false).opacity, Some(1.0));
+        assert_eq!(entry.sample(10.0, 10.0, false).opacity, Some(0.0));
+
+        assert_eq!(pose.other, vec![(4, CurveValue::Float(7.0))]);
+
+        // Samples outside of the keyframes hold the nearest one
+        assert_eq!(entry.sample(-1.0, 10.0, false).location, Some(Vector3::default()));
+        assert_eq!(entry.sample(5.0, 10.0, false).location, entry.sample(2.0, 10.0, false).location);
+
+        // Euler rotations are converted to quaternions
+        let euler_entry = AnmEntry {
+            curve_headers: vec![curve_header(1, CurveFormat::EulerXYZFixed, 1, 0xC)],
+            curves: vec![keys(
+                CurveFormat::EulerXYZFixed,
+                vec![Math::Vec3 {
+                    channels: (0.0, 0.0, std::f32::consts::FRAC_PI_2),
+                }],
+            )],
+            ..entry.clone()
+        };
+
+        let rotation = euler_entry.sample(0.0, 10.0, false).rotation.unwrap();
+        let half = std::f32::consts::FRAC_PI_4;
+        assert_vec_close(&[rotation.x, rotation.y, rotation.z, rotation.w], &[0.0, 0.0, half.sin(), half.cos()]);
+
+        // Looped animations wrap around, others are clamped to their length
+        let mut anm = NuccAnm {
+            frame_count: 400,
+            is_looped: true,
+            entries: vec![entry],
+            ..Default::default()
+        };
+
+        assert_eq!(anm.frame_length(), 4.0);
+        assert_eq!(anm.wrap_frame(5.0), 1.0);
+        assert_eq!(anm.wrap_frame(-1.0), 3.0);
+        assert_eq!(anm.sample(5.0), anm.sample(1.0));
+
+        // Entries sampled alone wrap the same way as through their anm
+        assert_eq!(anm.entries[0].sample(5.0, 4.0, true), anm.sample(5.0)[0]);
+        assert_eq!(anm.entries[0].sample(-1.0, 4.0, true), anm.entries[0].sample(3.0, 4.0, true));
+
+        anm.is_looped = false;
+        assert_eq!(anm.wrap_frame(5.0), 4.0);
+        assert_eq!(anm.wrap_frame(-1.0), 0.0);
+        assert_eq!(anm.entries[0].sample(5.0, 4.0, false), anm.sample(5.0)[0]);
+        assert_eq!(anm.sample(5.0), anm.sample(4.0));
+    }
+
+    #[test]
+    fn anm_bezier_test() {
+        let curve = Curve {
+            curve_format: CurveFormat::Vector3Bezier,
+            keyframes: vec![
+                Math::Vec3Bezier {
+                    frame: 0,
+                    channels: (0.0, 0.0, 0.0),
+                    in_tangent: (0.0, 0.0, 0.0),
+                    out_tangent: (2.0, 1.0, 0.0),
+                },
+                Math::Vec3Bezier {
+                    frame: 400,
+                    channels: (4.0, 4.0, 4.0),
+                    in_tangent: (2.0, 3.0, 4.0),
+                    out_tangent: (4.0, 4.0, 4.0),
+                },
+            ],
+        };
+
+        // Worked out by hand from the cubic bezier with the handles as absolute control points
+        let expected = [
+            (0.0, [0.0, 0.0, 0.0]),
+            (1.0, [1.1875, 0.90625, 0.625]),
+            (2.0, [2.0, 2.0, 2.0]),
+            (3.0, [2.8125, 3.09375, 3.375]),
+            (4.0, [4.0, 4.0, 4.0]),
+        ];
+
+        for (frame, value) in expected {
+            match curve.sample(frame) {
+                Some(CurveValue::Vec3(v)) => assert_vec_close(&[v.x, v.y, v.z], &value),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! # Encryption
+//! Xfbins with `XfbinHeader::encrypted` set have everything after the header encrypted.
+//! The header itself is always plain so that the flag can be read.
+//!
+//! The scheme that the games use isn't known, so it isn't implemented here. `XorCipher` is a
+//! generic repeating-key XOR, and files that use another scheme need their own `XfbinCipher`.
+//!
+use std::fmt;
+
+pub trait XfbinCipher: fmt::Debug + Send + Sync {
+    /// Decrypts `data` in place, where `offset` is the position of `data` in the encrypted region
+    fn decrypt(&self, data: &mut [u8], offset: u64);
+
+    /// Encrypts `data` in place, where `offset` is the position of `data` in the encrypted region
+    fn encrypt(&self, data: &mut [u8], offset: u64);
+}
+
+/// XOR scheme with a repeating key, keyed on the position in the encrypted region
+///
+/// This is not the scheme of any game, and no game key is bundled with the crate
+#[derive(Debug, Clone, PartialEq)]
+pub struct XorCipher {
+    pub key: Vec<u8>,
+}
+
+impl XorCipher {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    fn apply(&self, data: &mut [u8], offset: u64) {
+        if self.key.is_empty() {
+            return;
+        }
+
+        let key_len = self.key.len() as u64;
+
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte ^= self.key[((offset + i as u64) % key_len) as usize];
+        }
+    }
+}
+
+impl XfbinCipher for XorCipher {
+    fn decrypt(&self, data: &mut [u8], offset: u64) {
+        self.apply(data, offset)
+    }
+
+    fn encrypt(&self, data: &mut [u8], offset: u64) {
+        self.apply(data, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use crate::xfbin::{ReadOptions, WriteOptions, Xfbin};
+    use crate::xfbin_file::XFBIN_HEADER_SIZE;
+    use crate::{read_xfbin_buf, read_xfbin_buf_with, write_xfbin_buf, write_xfbin_buf_with, Result, XfbinError};
+    use std::sync::Arc;
+
+    #[test]
+    fn xor_cipher_test() {
+        let cipher = XorCipher::new(*b"KEY");
+
+        // The key is applied from the position in the encrypted region, so it wraps around mid-buffer
+        let mut data = [0x0B, 0x0C, 0x08, 0x06];
+        cipher.decrypt(&mut data, 1);
+        assert_eq!(&data, b"NUCC");
+
+        cipher.encrypt(&mut data, 1);
+        assert_eq!(data, [0x0B, 0x0C, 0x08, 0x06]);
+
+        // An empty key leaves the data as it is
+        let mut data = *b"NUCC";
+        XorCipher::new(Vec::new()).decrypt(&mut data, 0);
+        assert_eq!(&data, b"NUCC");
+    }
+
+    #[test]
+    fn encrypted_roundtrip_test() -> Result<()> {
+        let cipher: Arc<dyn XfbinCipher> = Arc::new(XorCipher::new(*b"xfbin-test-key"));
+
+        let plain = write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        })?;
+
+        let encrypted = write_xfbin_buf_with(
+            Xfbin {
+                encrypted: true,
+                pages: vec![page(fixture_structs(121))],
+                ..Default::default()
+            },
+            &WriteOptions {
+                cipher: Some(cipher.clone()),
+            },
+        )?;
+
+        // Only the encrypted flag in the header differs, everything after it is encrypted
+        assert_eq!(encrypted.len(), plain.len());
+        assert_eq!(encrypted[..8], plain[..8]);
+        assert_eq!(encrypted[8..10], [0, 1]);
+        assert_ne!(encrypted[XFBIN_HEADER_SIZE..], plain[XFBIN_HEADER_SIZE..]);
+
+        assert!(matches!(
+            read_xfbin_buf(encrypted.clone()),
+            Err(XfbinError::MissingCipher)
+        ));
+
+        let (xfbin, _) = read_xfbin_buf_with(
+            encrypted.clone(),
+            &ReadOptions {
+                cipher: Some(cipher.clone()),
+                ..Default::default()
+            },
+        )?;
+
+        assert!(xfbin.encrypted);
+        assert_eq!(xfbin.pages[0].structs.len(), fixture_structs(121).len());
+
+        assert!(matches!(
+            write_xfbin_buf(Xfbin {
+                encrypted: true,
+                ..Default::default()
+            }),
+            Err(XfbinError::MissingCipher)
+        ));
+
+        assert_eq!(
+            write_xfbin_buf_with(
+                xfbin,
+                &WriteOptions {
+                    cipher: Some(cipher)
+                }
+            )?,
+            encrypted
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn xor_cipher_offset_test() {
+        let cipher = XorCipher::new(*b"key");
+        let plain = bytes(64);
+
+        let mut whole = plain.clone();
+        cipher.encrypt(&mut whole, 0);
+
+        // Encrypting in two parts at their offsets must match encrypting everything at once
+        let mut split = plain.clone();
+        let (head, tail) = split.split_at_mut(17);
+        cipher.encrypt(head, 0);
+        cipher.encrypt(tail, 17);
+
+        assert_eq!(split, whole);
+
+        cipher.decrypt(&mut whole, 0);
+        assert_eq!(whole, plain);
+    }
+}
@@ -0,0 +1,439 @@
+//! # Curve encoding
+//! Conversion of curves between `CurveFormat`s, and passes that make them smaller.
+//!
+//! Curves are converted by sampling them, so any format can be converted to another one that holds
+//! the same kind of value. Euler rotations can also be converted to quaternion formats.
+//!
+use super::animation::{euler_to_quaternion, CurveValue, Interpolation, ValueKind, FRAME_SIZE};
+use super::error::{Result, XfbinError};
+use super::nucc_chunk::nucc_chunk_anm::{Curve, CurveFormat, Math};
+
+/// How the keyframes of a format are laid out
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layout {
+    Fixed,
+    Linear,
+    Table,
+}
+
+impl CurveFormat {
+    fn layout(&self) -> Option<Layout> {
+        Some(match self {
+            CurveFormat::Vector3Fixed
+            | CurveFormat::EulerXYZFixed
+            | CurveFormat::FloatFixed
+            | CurveFormat::Vector2Fixed => Layout::Fixed,
+            CurveFormat::Vector3Linear
+            | CurveFormat::EulerInterpolated
+            | CurveFormat::QuaternionLinear
+            | CurveFormat::FloatLinear
+            | CurveFormat::Vector2Linear
+            | CurveFormat::Vector3ShortLinear => Layout::Linear,
+            CurveFormat::OpacityShortTable
+            | CurveFormat::ScaleShortTable
+            | CurveFormat::QuaternionShortTable
+            | CurveFormat::ColorRGBTable
+            | CurveFormat::Vector3Table
+            | CurveFormat::FloatTable
+            | CurveFormat::QuaternionTable
+            | CurveFormat::FloatTableNoInterp
+            | CurveFormat::Vector3TableNoInterp
+            | CurveFormat::QuaternionShortTableNoInterp
+            | CurveFormat::OpacityShortTableNoInterp => Layout::Table,
+            CurveFormat::Vector3Bezier | CurveFormat::Unknown(_) => return None,
+        })
+    }
+}
+
+impl CurveValue {
+    fn components(&self) -> [f32; 4] {
+        match self {
+            CurveValue::Float(x) => [*x, 0.0, 0.0, 0.0],
+            CurveValue::Vec2(x, y) => [*x, *y, 0.0, 0.0],
+            CurveValue::Vec3(v) | CurveValue::Euler(v) | CurveValue::Color(v) => [v.x, v.y, v.z, 0.0],
+            CurveValue::Quaternion(q) => [q.x, q.y, q.z, q.w],
+        }
+    }
+
+    /// Largest difference between the components of two values
+    ///
+    /// A quaternion and its negation are the same rotation, so the closest of the two is used
+    fn difference(&self, other: &CurveValue) -> f32 {
+        let max_difference = |a: [f32; 4], b: [f32; 4]| {
+            a.iter().zip(b).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max)
+        };
+
+        let (a, b) = (self.components(), other.components());
+
+        match self {
+            CurveValue::Quaternion(_) => max_difference(a, b).min(max_difference(a, b.map(|c| -c))),
+            _ => max_difference(a, b),
+        }
+    }
+
+    /// The keyframe holding this value in a curve of `curve_format`
+    fn keyframe(&self, curve_format: &CurveFormat, frame: f32) -> Result<Math> {
+        let layout = curve_format
+            .layout()
+            .ok_or_else(|| XfbinError::UnsupportedCurveFormat(curve_format.clone()))?;
+        let frame = (frame * FRAME_SIZE).round() as i32;
+
+        Ok(match (self, layout) {
+            (CurveValue::Float(channels), Layout::Linear) => Math::FloatLinear { frame, channels: *channels },
+            (CurveValue::Float(channels), _) => Math::Float { channels: *channels },
+            (CurveValue::Vec2(x, y), Layout::Linear) => Math::Vec2Linear { frame, channels: (*x, *y) },
+            (CurveValue::Vec2(x, y), _) => Math::Vec2 { channels: (*x, *y) },
+            (CurveValue::Vec3(v) | CurveValue::Euler(v), Layout::Linear) => Math::Vec3Linear {
+                frame,
+                channels: (v.x, v.y, v.z),
+            },
+            (CurveValue::Vec3(v) | CurveValue::Euler(v), _) => Math::Vec3 {
+                channels: (v.x, v.y, v.z),
+            },
+            (CurveValue::Quaternion(q), Layout::Linear) => Math::Vec4Linear {
+                frame,
+                channels: (q.x, q.y, q.z, q.w),
+            },
+            (CurveValue::Quaternion(q), _) => Math::Vec4 {
+                channels: (q.x, q.y, q.z, q.w),
+            },
+            (CurveValue::Color(c), _) => {
+                let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+                Math::Color {
+                    channels: (channel(c.x), channel(c.y), channel(c.z)),
+                }
+            }
+        })
+    }
+}
+
+impl Curve {
+    /// Re-encodes the curve in `curve_format`
+    ///
+    /// Table formats get a keyframe for each of the frames `0..frame_count`, and so do linear formats
+    /// unless the curve is already linear, in which case its keyframes are kept where they are.
+    /// Fixed formats hold the value at frame 0.
+    pub fn convert(&self, curve_format: CurveFormat, frame_count: usize) -> Result<Curve> {
+        let incompatible = || XfbinError::IncompatibleCurveFormat {
+            from: self.curve_format.clone(),
+            to: curve_format.clone(),
+        };
+
+        let from_kind = self.curve_format.value_kind().ok_or_else(incompatible)?;
+        let to_kind = curve_format.value_kind().ok_or_else(incompatible)?;
+        let layout = curve_format.layout().ok_or_else(incompatible)?;
+
+        let to_quaternion = match (from_kind, to_kind) {
+            (ValueKind::Euler, ValueKind::Quaternion) => true,
+            (from_kind, to_kind) if from_kind == to_kind => false,
+            _ => return Err(incompatible()),
+        };
+
+        let frames = match layout {
+            Layout::Fixed => vec![0.0],
+            Layout::Linear if self.curve_format.layout() == Some(Layout::Linear) => self.key_frames(),
+            _ => (0..frame_count).map(|frame| frame as f32).collect(),
+        };
+
+        let keyframes = frames
+            .into_iter()
+            .filter_map(|frame| self.sample(frame).map(|value| (frame, value)))
+            .map(|(frame, value)| match value {
+                CurveValue::Euler(euler) if to_quaternion => {
+                    CurveValue::Quaternion(euler_to_quaternion(&euler)).keyframe(&curve_format, frame)
+                }
+                value => value.keyframe(&curve_format, frame),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Curve {
+            curve_format,
+            keyframes,
+        })
+    }
+
+    /// Samples the curve into the table format of its kind of value, e.g. a `Vector3Linear` curve
+    /// into a `Vector3Table`
+    pub fn bake(&self, frame_count: usize) -> Result<Curve> {
+        let curve_format = match self.curve_format.value_kind() {
+            Some(ValueKind::Float) => CurveFormat::FloatTable,
+            Some(ValueKind::Vec3) => CurveFormat::Vector3Table,
+            Some(ValueKind::Quaternion | ValueKind::Euler) => CurveFormat::QuaternionTable,
+            Some(ValueKind::Color) => CurveFormat::ColorRGBTable,
+            Some(ValueKind::Vec2) | None => return Err(XfbinError::UnsupportedCurveFormat(self.curve_format.clone())),
+        };
+
+        self.convert(curve_format, frame_count)
+    }
+
+    /// A fixed curve holding the value of this one, if it stays within `tolerance` of its first keyframe
+    ///
+    /// Returns `None` if the curve changes, or if there is no fixed format for its kind of value
+    pub fn collapse_constant(&self, tolerance: f32) -> Option<Curve> {
+        let curve_format = match self.curve_format.value_kind()? {
+            ValueKind::Float => CurveFormat::FloatFixed,
+            ValueKind::Vec2 => CurveFormat::Vector2Fixed,
+            ValueKind::Vec3 => CurveFormat::Vector3Fixed,
+            ValueKind::Euler => CurveFormat::EulerXYZFixed,
+            ValueKind::Quaternion | ValueKind::Color => return None,
+        };
+
+        let first = self.sample(0.0)?;
+        let is_constant = self
+            .key_frames()
+            .into_iter()
+            .filter_map(|frame| self.sample(frame))
+            .all(|value| value.difference(&first) <= tolerance);
+
+        if !is_constant {
+            return None;
+        }
+
+        Some(Curve {
+            curve_format: curve_format.clone(),
+            keyframes: vec![first.keyframe(&curve_format, 0.0).ok()?],
+        })
+    }
+
+    /// Removes keyframes of a linear curve that can be interpolated from their neighbours, so that
+    /// no keyframe moves by more than `tolerance`
+    ///
+    /// The first and last keyframes are always kept, and every keyframe has to match the curve's format
+    pub fn reduce(&self, tolerance: f32) -> Result<Curve> {
+        let unsupported = || XfbinError::UnsupportedCurveFormat(self.curve_format.clone());
+
+        if self.curve_format.layout() != Some(Layout::Linear)
+            || self.curve_format.interpolation() != Interpolation::Linear
+        {
+            return Err(unsupported());
+        }
+
+        let frames = self
+            .keyframes
+            .iter()
+            .map(|keyframe| linear_frame(&self.curve_format, keyframe).map(|frame| frame as f32 / FRAME_SIZE))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(unsupported)?;
+
+        if frames.len() < 3 {
+            return Ok(self.clone());
+        }
+
+        let values = frames
+            .iter()
+            .map(|&frame| self.sample(frame))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(unsupported)?;
+
+        let mut kept = vec![0];
+
+        for next in 2..frames.len() {
+            let start = *kept.last().unwrap();
+            let segment = Curve {
+                curve_format: self.curve_format.clone(),
+                keyframes: vec![self.keyframes[start].clone(), self.keyframes[next].clone()],
+            };
+
+            // Keep the keyframe before `next` if skipping it would move any keyframe in between too far
+            let fits = (start + 1..next).all(|skipped| {
+                segment
+                    .sample(frames[skipped])
+                    .is_some_and(|value| value.difference(&values[skipped]) <= tolerance)
+            });
+
+            if !fits {
+                kept.push(next - 1);
+            }
+        }
+
+        kept.push(frames.len() - 1);
+
+        Ok(Curve {
+            curve_format: self.curve_format.clone(),
+            keyframes: kept.into_iter().map(|index| self.keyframes[index].clone()).collect(),
+        })
+    }
+}
+
+/// The stored frame of a keyframe of a linear format, if it's the keyframe variant of that format
+fn linear_frame(curve_format: &CurveFormat, keyframe: &Math) -> Option<i32> {
+    match (curve_format, keyframe) {
+        (
+            CurveFormat::Vector3Linear | CurveFormat::EulerInterpolated | CurveFormat::Vector3ShortLinear,
+            Math::Vec3Linear { frame, .. },
+        )
+        | (CurveFormat::QuaternionLinear, Math::Vec4Linear { frame, .. })
+        | (CurveFormat::FloatLinear, Math::FloatLinear { frame, .. })
+        | (CurveFormat::Vector2Linear, Math::Vec2Linear { frame, .. }) => Some(*frame),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc_chunk::nucc_chunk_anm::{AnmEntry, EntryFormat};
+    use crate::nucc_chunk::{NuccChunkAnm, NuccChunkType};
+    use crate::test_util::*;
+
+    #[test]
+    fn curve_encoding_test() -> Result<()> {
+        let location = Curve {
+            curve_format: CurveFormat::Vector3Linear,
+            keyframes: vec![
+                Math::Vec3Linear {
+                    frame: 0,
+                    channels: (0.0, 0.0, 0.0),
+                },
+                Math::Vec3Linear {
+                    frame: 200,
+                    channels: (2.0, 4.0, 6.0),
+                },
+            ],
+        };
+
+        // Linear keyframes are baked into one value per frame
+        let baked = location.bake(3)?;
+        assert_eq!(baked.curve_format, CurveFormat::Vector3Table);
+        assert_eq!(
+            baked.keyframes,
+            vec![
+                Math::Vec3 { channels: (0.0, 0.0, 0.0) },
+                Math::Vec3 { channels: (1.0, 2.0, 3.0) },
+                Math::Vec3 { channels: (2.0, 4.0, 6.0) },
+            ]
+        );
+        assert_eq!(baked.header(0), curve_header(0, CurveFormat::Vector3Table, 3, 36));
+
+        // Converting back to a linear format places a keyframe on every frame
+        let linear = baked.convert(CurveFormat::Vector3Linear, 3)?;
+        assert_eq!(linear.keyframes.len(), 3);
+        assert_eq!(linear.reduce(1e-4)?, location);
+
+        assert!(matches!(
+            location.convert(CurveFormat::FloatTable, 3),
+            Err(XfbinError::IncompatibleCurveFormat { .. })
+        ));
+        assert!(baked.reduce(1e-4).is_err());
+
+        // Quaternion tables are reduced to fixed-point
+        let half = std::f32::consts::FRAC_PI_4;
+        let rotation = Curve {
+            curve_format: CurveFormat::QuaternionTable,
+            keyframes: vec![
+                Math::Vec4 { channels: (0.0, 0.0, 0.0, 1.0) },
+                Math::Vec4 { channels: (0.0, 0.0, half.sin(), half.cos()) },
+            ],
+        };
+
+        let short_rotation = rotation.convert(CurveFormat::QuaternionShortTable, 2)?;
+        assert_eq!(short_rotation.header(1), curve_header(1, CurveFormat::QuaternionShortTable, 2, 16));
+
+        // Euler rotations can be stored as quaternions
+        let euler = Curve {
+            curve_format: CurveFormat::EulerXYZFixed,
+            keyframes: vec![Math::Vec3 {
+                channels: (0.0, 0.0, std::f32::consts::FRAC_PI_2),
+            }],
+        };
+
+        match &euler.convert(CurveFormat::QuaternionTable, 1)?.keyframes[..] {
+            [Math::Vec4 { channels: (x, y, z, w) }] => assert_vec_close(&[*x, *y, *z, *w], &[0.0, 0.0, half.sin(), half.cos()]),
+            keyframes => panic!("unexpected keyframes {:?}", keyframes),
+        }
+
+        // Constant curves collapse into a fixed value
+        let scale = Curve {
+            curve_format: CurveFormat::ScaleShortTable,
+            keyframes: vec![Math::Vec3 { channels: (1.0, 1.0, 1.0) }; 4],
+        };
+
+        let mut noisy = scale.clone();
+        noisy.keyframes[2] = Math::Vec3 { channels: (1.0, 1.0005, 1.0) };
+
+        let fixed = noisy.collapse_constant(1e-3).unwrap();
+        assert_eq!(fixed.curve_format, CurveFormat::Vector3Fixed);
+        assert_eq!(fixed.keyframes, vec![Math::Vec3 { channels: (1.0, 1.0, 1.0) }]);
+
+        assert!(noisy.collapse_constant(1e-4).is_none());
+        assert!(location.collapse_constant(1e-3).is_none());
+        assert!(rotation.collapse_constant(1.0).is_none());
+
+        // Keyframes that can be interpolated from their neighbours are removed
+        let opacity = Curve {
+            curve_format: CurveFormat::FloatLinear,
+            keyframes: [0.0, 1.0, 2.0, 3.0, 10.0]
+                .into_iter()
+                .enumerate()
+                .map(|(frame, channels)| Math::FloatLinear {
+                    frame: frame as i32 * 100,
+                    channels,
+                })
+                .collect(),
+        };
+
+        let frames = |curve: &Curve| {
+            curve
+                .keyframes
+                .iter()
+                .map(|keyframe| match keyframe {
+                    Math::FloatLinear { frame, .. } => *frame,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(frames(&opacity.reduce(0.01)?), vec![0, 300, 400]);
+        assert_eq!(frames(&opacity.reduce(5.0)?), vec![0, 400]);
+
+        // Curves with too few keyframes to remove any are kept as they are
+        let empty = Curve {
+            curve_format: CurveFormat::Vector3Linear,
+            keyframes: Vec::new(),
+        };
+
+        assert_eq!(empty.reduce(0.1)?, empty);
+        assert_eq!(location.reduce(100.0)?, location);
+
+        // Keyframes of another format are rejected instead of being misaligned with their frames
+        let mut mixed = opacity.clone();
+        mixed.keyframes[1] = Math::Float { channels: 1.0 };
+
+        assert!(mixed.reduce(0.01).is_err());
+
+        // Converted curves are written with headers that match them, keeping the curve indices
+        let entry = AnmEntry {
+            coord: coord(0, 0),
+            entry_format: EntryFormat::Coord,
+            curve_headers: vec![
+                curve_header(0, CurveFormat::Vector3Linear, 2, 32),
+                curve_header(1, CurveFormat::QuaternionTable, 2, 32),
+                curve_header(2, CurveFormat::ScaleShortTable, 4, 24),
+            ],
+            curves: vec![baked, short_rotation, fixed],
+        };
+
+        let chunk = NuccChunkAnm {
+            version: 121,
+            entries: vec![entry.clone()],
+            ..Default::default()
+        };
+
+        let data = NuccChunkType::write_data(Box::new(chunk), 121)?;
+        let reread = reread_chunk(NuccChunkType::NuccChunkAnm, data)?;
+        let reread = reread.downcast_ref::<NuccChunkAnm>().unwrap();
+
+        assert_eq!(
+            reread.entries[0].curve_headers,
+            vec![
+                curve_header(0, CurveFormat::Vector3Table, 3, 36),
+                curve_header(1, CurveFormat::QuaternionShortTable, 2, 16),
+                curve_header(2, CurveFormat::Vector3Fixed, 1, 12),
+            ]
+        );
+        assert_eq!(reread.entries[0].curves[0], entry.curves[0]);
+        assert_eq!(reread.entries[0].curves[2], entry.curves[2]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,459 @@
+//! # DDS
+//! Conversion between `NutTexture` and DirectDraw Surface files.
+//!
+//! Texture data is laid out the same way in both, each face of a cubemap followed by its mipmaps,
+//! so it's copied as is.
+//!
+use binrw::{binrw, io::Cursor, BinReaderExt, BinResult, BinWrite, ReadOptions};
+use std::io::{self, Read, Seek};
+
+use super::error::{Result, XfbinError};
+use super::nucc_chunk::nucc_chunk_texture::{NutPixelFormat, NutTexture};
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xFC00;
+
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+#[binrw]
+#[brw(little, magic = b"DDS ")]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Dds {
+    pub header: DdsHeader,
+
+    #[br(if(header.pixel_format.four_cc == *b"DX10"))]
+    pub dx10_header: Option<DdsHeaderDx10>,
+
+    #[br(parse_with = read_to_end)]
+    pub data: Vec<u8>,
+}
+
+#[binrw]
+#[brw(magic = 124u32)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DdsHeader {
+    pub flags: u32,
+    pub height: u32,
+    pub width: u32,
+    pub pitch_or_linear_size: u32,
+    pub depth: u32,
+
+    #[brw(pad_after = 44)]
+    pub mipmap_count: u32,
+
+    pub pixel_format: DdsPixelFormat,
+
+    pub caps: u32,
+    pub caps2: u32,
+    pub caps3: u32,
+
+    #[brw(pad_after = 4)]
+    pub caps4: u32,
+}
+
+#[binrw]
+#[brw(magic = 32u32)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DdsPixelFormat {
+    pub flags: u32,
+    pub four_cc: [u8; 4],
+    pub rgb_bit_count: u32,
+    pub r_bit_mask: u32,
+    pub g_bit_mask: u32,
+    pub b_bit_mask: u32,
+    pub a_bit_mask: u32,
+}
+
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DdsHeaderDx10 {
+    pub dxgi_format: u32,
+    pub resource_dimension: u32,
+    pub misc_flag: u32,
+    pub array_size: u32,
+    pub misc_flags2: u32,
+}
+
+fn read_to_end<R: Read + Seek>(reader: &mut R, _: &ReadOptions, _: ()) -> BinResult<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+impl DdsPixelFormat {
+    fn from_nut(format: NutPixelFormat) -> Self {
+        let four_cc = |four_cc: &[u8; 4]| Self {
+            flags: DDPF_FOURCC,
+            four_cc: *four_cc,
+            ..Default::default()
+        };
+
+        let rgb = |rgb_bit_count, r_bit_mask, g_bit_mask, b_bit_mask, a_bit_mask| Self {
+            flags: DDPF_RGB | if a_bit_mask != 0 { DDPF_ALPHAPIXELS } else { 0 },
+            four_cc: [0; 4],
+            rgb_bit_count,
+            r_bit_mask,
+            g_bit_mask,
+            b_bit_mask,
+            a_bit_mask,
+        };
+
+        match format {
+            NutPixelFormat::Dxt1 => four_cc(b"DXT1"),
+            NutPixelFormat::Dxt3 => four_cc(b"DXT3"),
+            NutPixelFormat::Dxt5 => four_cc(b"DXT5"),
+            NutPixelFormat::Bc4 => four_cc(b"ATI1"),
+            NutPixelFormat::Bc5 => four_cc(b"ATI2"),
+            NutPixelFormat::B5G5R5A1 => rgb(16, 0x7C00, 0x03E0, 0x001F, 0x8000),
+            NutPixelFormat::B4G4R4A4 => rgb(16, 0x0F00, 0x00F0, 0x000F, 0xF000),
+            NutPixelFormat::B5G6R5 => rgb(16, 0xF800, 0x07E0, 0x001F, 0),
+            NutPixelFormat::R8G8B8A8 => rgb(32, 0x000000FF, 0x0000FF00, 0x00FF0000, 0xFF000000),
+            NutPixelFormat::B8G8R8A8 => rgb(32, 0x00FF0000, 0x0000FF00, 0x000000FF, 0xFF000000),
+        }
+    }
+}
+
+impl Dds {
+    pub fn format(&self) -> Result<NutPixelFormat> {
+        let pixel_format = &self.header.pixel_format;
+
+        if pixel_format.flags & DDPF_FOURCC != 0 {
+            return match &pixel_format.four_cc {
+                b"DXT1" => Ok(NutPixelFormat::Dxt1),
+                b"DXT3" => Ok(NutPixelFormat::Dxt3),
+                b"DXT5" => Ok(NutPixelFormat::Dxt5),
+                b"ATI1" | b"BC4U" => Ok(NutPixelFormat::Bc4),
+                b"ATI2" | b"BC5U" => Ok(NutPixelFormat::Bc5),
+                b"DX10" => {
+                    let dxgi_format = self.dx10_header.as_ref().map_or(0, |h| h.dxgi_format);
+
+                    match dxgi_format {
+                        71 | 72 => Ok(NutPixelFormat::Dxt1),
+                        74 | 75 => Ok(NutPixelFormat::Dxt3),
+                        77 | 78 => Ok(NutPixelFormat::Dxt5),
+                        80 => Ok(NutPixelFormat::Bc4),
+                        83 => Ok(NutPixelFormat::Bc5),
+                        28 | 29 => Ok(NutPixelFormat::R8G8B8A8),
+                        87 | 91 => Ok(NutPixelFormat::B8G8R8A8),
+                        85 => Ok(NutPixelFormat::B5G6R5),
+                        86 => Ok(NutPixelFormat::B5G5R5A1),
+                        115 => Ok(NutPixelFormat::B4G4R4A4),
+                        any => Err(XfbinError::UnsupportedDdsFormat(format!("DXGI format {}", any))),
+                    }
+                }
+                any => Err(XfbinError::UnsupportedDdsFormat(format!(
+                    "FourCC {}",
+                    String::from_utf8_lossy(any)
+                ))),
+            };
+        }
+
+        [
+            NutPixelFormat::B5G5R5A1,
+            NutPixelFormat::B4G4R4A4,
+            NutPixelFormat::B5G6R5,
+            NutPixelFormat::R8G8B8A8,
+            NutPixelFormat::B8G8R8A8,
+        ]
+        .into_iter()
+        .find(|&format| {
+            let expected = DdsPixelFormat::from_nut(format);
+
+            pixel_format.rgb_bit_count == expected.rgb_bit_count
+                && pixel_format.r_bit_mask == expected.r_bit_mask
+                && pixel_format.g_bit_mask == expected.g_bit_mask
+                && pixel_format.b_bit_mask == expected.b_bit_mask
+                && (pixel_format.flags & DDPF_ALPHAPIXELS == 0 || pixel_format.a_bit_mask == expected.a_bit_mask)
+        })
+        .ok_or_else(|| {
+            XfbinError::UnsupportedDdsFormat(format!(
+                "{}-bit RGB with masks {:#x} {:#x} {:#x} {:#x}",
+                pixel_format.rgb_bit_count,
+                pixel_format.r_bit_mask,
+                pixel_format.g_bit_mask,
+                pixel_format.b_bit_mask,
+                pixel_format.a_bit_mask
+            ))
+        })
+    }
+
+    pub fn mipmap_count(&self) -> usize {
+        if self.header.flags & DDSD_MIPMAPCOUNT != 0 {
+            self.header.mipmap_count.max(1) as usize
+        } else {
+            1
+        }
+    }
+
+    /// Whether the file is a cubemap, from either the caps2 of the header or the misc flag of the DX10 header
+    pub fn is_cubemap(&self) -> bool {
+        self.header.caps2 & DDSCAPS2_CUBEMAP != 0
+            || self
+                .dx10_header
+                .as_ref()
+                .is_some_and(|h| h.misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0)
+    }
+
+    /// Cubemap flags of the file in the form of caps2, DX10 cubemaps always have all six faces
+    fn cubemap_flags(&self) -> u32 {
+        match self.header.caps2 & DDSCAPS2_CUBEMAP_ALL_FACES {
+            _ if !self.is_cubemap() => 0,
+            0 => DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES,
+            faces => DDSCAPS2_CUBEMAP | faces,
+        }
+    }
+}
+
+/// Sizes of each mipmap level of a single face
+fn mipmap_sizes(format: NutPixelFormat, width: usize, height: usize, mipmap_count: usize) -> Vec<usize> {
+    (0..mipmap_count)
+        .map(|level| format.surface_size((width >> level).max(1), (height >> level).max(1)))
+        .collect()
+}
+
+impl NutTexture {
+    pub fn to_dds(&self) -> Result<Vec<u8>> {
+        let format = self.format()?;
+
+        let width = self.width as u32;
+        let height = self.height as u32;
+        let mipmap_count = self.mipmap_count.max(1) as u32;
+
+        let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+        let mut caps = DDSCAPS_TEXTURE;
+        let mut caps2 = 0;
+
+        let pitch_or_linear_size = if format.is_compressed() {
+            flags |= DDSD_LINEARSIZE;
+            format.surface_size(width as usize, height as usize) as u32
+        } else {
+            flags |= DDSD_PITCH;
+            width * format.block_size() as u32
+        };
+
+        if mipmap_count > 1 {
+            flags |= DDSD_MIPMAPCOUNT;
+            caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+        }
+
+        if self.is_cubemap() {
+            caps |= DDSCAPS_COMPLEX;
+            caps2 |= DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES;
+        }
+
+        let dds = Dds {
+            header: DdsHeader {
+                flags,
+                height,
+                width,
+                pitch_or_linear_size,
+                depth: 0,
+                mipmap_count,
+                pixel_format: DdsPixelFormat::from_nut(format),
+                caps,
+                caps2,
+                caps3: 0,
+                caps4: 0,
+            },
+            dx10_header: None,
+            data: self.texture_data.clone(),
+        };
+
+        let mut cursor = Cursor::new(Vec::new());
+        dds.write_le(&mut cursor)?;
+
+        Ok(cursor.into_inner())
+    }
+
+    /// Creates a texture from a DDS file, the GIDX hash is left as 0
+    pub fn from_dds(buf: &[u8]) -> Result<Self> {
+        let dds = Cursor::new(buf).read_le::<Dds>()?;
+
+        let format = dds.format()?;
+        let width = dds.header.width as usize;
+        let height = dds.header.height as usize;
+        let mipmap_count = dds.mipmap_count();
+
+        let sizes = mipmap_sizes(format, width, height, mipmap_count);
+        let face_size = sizes.iter().sum::<usize>();
+        let faces = if dds.is_cubemap() { 6 } else { 1 };
+        let data_size = face_size * faces;
+
+        let texture_data = dds
+            .data
+            .get(..data_size)
+            .ok_or_else(|| XfbinError::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?
+            .to_vec();
+
+        // Single mipmap textures have no mipmap size table
+        let mipmaps = if mipmap_count > 1 {
+            sizes.into_iter().map(|size| size as u32).collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            mipmap_count: mipmap_count as u16,
+            pixel_format: format as u16,
+            width: width as u16,
+            height: height as u16,
+            caps2: dds.cubemap_flags(),
+            cubemap_format: dds.cubemap_flags(),
+            cubemap_sizes: dds.is_cubemap().then_some([face_size as u32, face_size as u32, 0, 0]),
+            mipmaps,
+            texture_data,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::bytes;
+
+    #[test]
+    fn nut_dds_test() -> Result<()> {
+        let formats = [
+            NutPixelFormat::Dxt1,
+            NutPixelFormat::Dxt3,
+            NutPixelFormat::Dxt5,
+            NutPixelFormat::Bc4,
+            NutPixelFormat::Bc5,
+            NutPixelFormat::B5G5R5A1,
+            NutPixelFormat::B4G4R4A4,
+            NutPixelFormat::B5G6R5,
+            NutPixelFormat::R8G8B8A8,
+            NutPixelFormat::B8G8R8A8,
+        ];
+
+        for format in formats {
+            let sizes = [format.surface_size(16, 8), format.surface_size(8, 4), format.surface_size(4, 2)];
+
+            let texture = NutTexture {
+                mipmap_count: 3,
+                pixel_format: format as u16,
+                width: 16,
+                height: 8,
+                mipmaps: sizes.iter().map(|&size| size as u32).collect(),
+                texture_data: bytes(sizes.iter().sum()),
+                ..Default::default()
+            };
+
+            let dds = texture.to_dds()?;
+            assert_eq!(&dds[..4], b"DDS ");
+            assert_eq!(dds.len(), 128 + texture.texture_data.len());
+
+            assert_eq!(NutTexture::from_dds(&dds)?, texture, "{:?}", format);
+        }
+
+        assert!(matches!(
+            NutTexture {
+                pixel_format: 99,
+                ..Default::default()
+            }
+            .to_dds(),
+            Err(XfbinError::UnsupportedPixelFormat(99))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn dds_cubemap_test() -> Result<()> {
+        // Six faces of 8x8 DXT1 with two mipmaps each
+        let face_size = 32 + 8;
+        let cubemap = NutTexture {
+            mipmap_count: 2,
+            pixel_format: NutPixelFormat::Dxt1 as u16,
+            width: 8,
+            height: 8,
+            caps2: DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES,
+            cubemap_format: DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES,
+            cubemap_sizes: Some([face_size, face_size, 0, 0]),
+            mipmaps: vec![32, 8],
+            texture_data: bytes(face_size as usize * 6),
+            ..Default::default()
+        };
+
+        // The face sizes come before the mipmap size table in the NUT header
+        let mut cursor = Cursor::new(Vec::new());
+        cubemap.write_be(&mut cursor)?;
+        let nut = cursor.into_inner();
+
+        assert_eq!(cubemap.header_size(), 0x50 + 0x10 + 0x10);
+        assert_eq!(nut[0x30..0x34], face_size.to_be_bytes());
+        assert_eq!(nut[0x40..0x44], 32u32.to_be_bytes());
+        assert_eq!(Cursor::new(&nut).read_be::<NutTexture>()?, cubemap);
+
+        let dds = cubemap.to_dds()?;
+        let header = Cursor::new(&dds).read_le::<Dds>()?.header;
+        assert_eq!(header.caps2, DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES);
+
+        assert_eq!(NutTexture::from_dds(&dds)?, cubemap);
+
+        // DX10 files mark cubemaps with a misc flag instead of caps2
+        let mut dds = Cursor::new(dds).read_le::<Dds>()?;
+        dds.header.caps2 = 0;
+        dds.header.pixel_format.four_cc = *b"DX10";
+        dds.dx10_header = Some(DdsHeaderDx10 {
+            dxgi_format: 71,
+            resource_dimension: 3,
+            misc_flag: DDS_RESOURCE_MISC_TEXTURECUBE,
+            array_size: 1,
+            ..Default::default()
+        });
+
+        let mut cursor = Cursor::new(Vec::new());
+        dds.write_le(&mut cursor)?;
+
+        assert_eq!(NutTexture::from_dds(&cursor.into_inner())?, cubemap);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dds_dx10_test() -> Result<()> {
+        let texture = NutTexture {
+            pixel_format: NutPixelFormat::Bc5 as u16,
+            width: 8,
+            height: 8,
+            texture_data: bytes(64),
+            ..Default::default()
+        };
+
+        // Rewrite the legacy header as a DX10 header, as most tools write BC5
+        let mut dds = Cursor::new(texture.to_dds()?).read_le::<Dds>()?;
+        dds.header.pixel_format.four_cc = *b"DX10";
+        dds.dx10_header = Some(DdsHeaderDx10 {
+            dxgi_format: 83,
+            resource_dimension: 3,
+            array_size: 1,
+            ..Default::default()
+        });
+
+        let mut cursor = Cursor::new(Vec::new());
+        dds.write_le(&mut cursor)?;
+
+        assert_eq!(NutTexture::from_dds(&cursor.into_inner())?, texture);
+
+        Ok(())
+    }
+}
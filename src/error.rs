@@ -0,0 +1,108 @@
+use std::io;
+use thiserror::Error;
+
+use super::nucc::NuccStructInfo;
+use super::nucc_chunk::nucc_chunk_anm::CurveFormat;
+use super::nucc_chunk::NuccChunkType;
+
+pub type Result<T> = std::result::Result<T, XfbinError>;
+
+#[derive(Debug, Error)]
+pub enum XfbinError {
+    #[error("Bad magic at offset {pos:#x}, expected \"NUCC\"")]
+    BadMagic { pos: u64 },
+
+    #[error("Truncated or malformed xfbin index")]
+    TruncatedIndex(#[source] binrw::Error),
+
+    #[error("{table} index {index} is out of range (length {len})")]
+    IndexOutOfRange {
+        table: &'static str,
+        index: usize,
+        len: usize,
+    },
+
+    #[error("Failed to parse chunk \"{chunk_name}\" of type \"{chunk_type}\" at offset {offset:#x}")]
+    ChunkParse {
+        chunk_name: String,
+        chunk_type: String,
+        offset: u64,
+        #[source]
+        source: Box<XfbinError>,
+    },
+
+    #[error("Downcast mismatch: expected {expected}, found {found}")]
+    DowncastMismatch {
+        expected: &'static str,
+        found: NuccChunkType,
+    },
+
+    #[error("Unexpected chunk type {0}")]
+    UnexpectedChunkType(NuccChunkType),
+
+    #[error("{0} is not in the page's chunk maps")]
+    UnmappedStructInfo(NuccStructInfo),
+
+    #[error("Reference \"{0}\" is missing from the page's references or out of order")]
+    UnmappedReference(String),
+
+    #[error("{0} is already in the page")]
+    DuplicateStruct(NuccStructInfo),
+
+    #[error("Reference \"{0}\" points to a chunk that isn't in the page")]
+    DanglingReference(String),
+
+    #[error("{struct_info} is still bound by chunk \"{bound_by}\"")]
+    BoundStruct {
+        struct_info: NuccStructInfo,
+        bound_by: String,
+    },
+
+    #[error("The xfbin is encrypted but no cipher was given")]
+    MissingCipher,
+
+    #[error("Unsupported NUT pixel format {0}")]
+    UnsupportedPixelFormat(u16),
+
+    #[error("A {width}x{height} image can't be stored in a NUT texture")]
+    InvalidImageSize { width: u32, height: u32 },
+
+    #[error("Unsupported DDS format: {0}")]
+    UnsupportedDdsFormat(String),
+
+    #[error("Unsupported curve format {0:?}")]
+    UnsupportedCurveFormat(CurveFormat),
+
+    #[error("Frame {0} doesn't fit in the keyframes of a Vector3ShortLinear curve")]
+    FrameOutOfRange(i32),
+
+    #[error("An anm entry has {curves} curves but {curve_headers} curve headers")]
+    CurveCountMismatch { curves: usize, curve_headers: usize },
+
+    #[error("A {from:?} curve can't be converted to {to:?}")]
+    IncompatibleCurveFormat { from: CurveFormat, to: CurveFormat },
+
+    #[error(transparent)]
+    Png(#[from] png::EncodingError),
+
+    #[error(transparent)]
+    BinRw(#[from] binrw::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl XfbinError {
+    /// Creates a `DowncastMismatch` for a boxed chunk or struct that was not a `T`
+    pub(crate) fn downcast_mismatch<T>(found: NuccChunkType) -> Self {
+        XfbinError::DowncastMismatch {
+            expected: std::any::type_name::<T>(),
+            found,
+        }
+    }
+
+    /// Creates an `IndexOutOfRange` for a lookup into one of the index tables
+    pub(crate) fn out_of_range(table: &'static str, index: usize, len: usize) -> Self {
+        XfbinError::IndexOutOfRange { table, index, len }
+    }
+}
@@ -1,36 +1,84 @@
+pub mod animation;
+pub mod cipher;
+pub mod curve;
+pub mod dds;
+pub mod error;
 pub mod nucc;
 pub mod nucc_chunk;
 pub mod page;
+pub mod query;
+pub mod reader;
+pub mod roundtrip;
+pub mod texture;
+pub mod view;
+pub mod writer;
 pub mod xfbin;
 pub mod xfbin_file;
 
-use anyhow::{Context, Result};
-use binrw::{io::Cursor, BinReaderExt, BinWrite};
+#[cfg(test)]
+mod test_util;
+
+use binrw::{io::Cursor, BinWrite};
 use std::{
     fs::{self, File},
-    io::Write,
+    io::{BufReader, Write},
     path::Path,
 };
 
-pub use xfbin::Xfbin;
-use xfbin_file::XfbinFile;
+pub use cipher::{XfbinCipher, XorCipher};
+pub use error::{Result, XfbinError};
+pub use reader::{XfbinChunkDescriptor, XfbinReader};
+pub use roundtrip::{verify_roundtrip, verify_roundtrip_with, RoundTripReport};
+pub use view::{XfbinChunkView, XfbinView};
+pub use writer::{SpoolFile, XfbinWriter};
+pub use xfbin::{ChunkDiagnostic, ReadOptions, WriteOptions, Xfbin};
+use xfbin_file::{XfbinFile, XfbinHeader, XFBIN_HEADER_SIZE};
 
 pub fn read_xfbin(filepath: &dyn AsRef<Path>) -> Result<Xfbin> {
     read_xfbin_buf(fs::read(filepath)?)
 }
 
 pub fn read_xfbin_buf(buf: Vec<u8>) -> Result<Xfbin> {
+    read_xfbin_buf_with(buf, &ReadOptions::default()).map(|(xfbin, _)| xfbin)
+}
+
+/// Reads an xfbin with the given options, returning the diagnostics of any chunks kept as raw bytes
+pub fn read_xfbin_with(
+    filepath: &dyn AsRef<Path>,
+    options: &ReadOptions,
+) -> Result<(Xfbin, Vec<ChunkDiagnostic>)> {
+    read_xfbin_buf_with(fs::read(filepath)?, options)
+}
+
+/// Opens an xfbin for streaming, only the header and index are read until chunks are requested
+pub fn open_xfbin(filepath: &dyn AsRef<Path>) -> Result<XfbinReader<BufReader<File>>> {
+    XfbinReader::new(BufReader::new(File::open(filepath)?))
+}
+
+pub fn read_xfbin_buf_with(
+    mut buf: Vec<u8>,
+    options: &ReadOptions,
+) -> Result<(Xfbin, Vec<ChunkDiagnostic>)> {
+    let header = XfbinHeader::read_checked(&mut Cursor::new(&buf))?;
+
+    if header.encrypted != 0 {
+        let cipher = options.cipher.as_ref().ok_or(XfbinError::MissingCipher)?;
+        cipher.decrypt(&mut buf[XFBIN_HEADER_SIZE..], 0);
+    }
+
     let mut reader = std::io::Cursor::new(buf);
 
-    let xfbin_file = reader
-        .read_be::<XfbinFile>()
-        .with_context(|| format!("Failed to read xfbin from buffer"))?;
+    let xfbin_file = XfbinFile::read(&mut reader)?;
 
-    Ok(xfbin_file.into())
+    Xfbin::from_file(xfbin_file, options)
 }
 
 pub fn write_xfbin(xfbin: Xfbin, filepath: &dyn AsRef<Path>) -> Result<()> {
-    let buf = write_xfbin_buf(xfbin)?;
+    write_xfbin_with(xfbin, filepath, &WriteOptions::default())
+}
+
+pub fn write_xfbin_with(xfbin: Xfbin, filepath: &dyn AsRef<Path>, options: &WriteOptions) -> Result<()> {
+    let buf = write_xfbin_buf_with(xfbin, options)?;
 
     let mut file = File::create(filepath)?;
 
@@ -38,105 +86,28 @@ pub fn write_xfbin(xfbin: Xfbin, filepath: &dyn AsRef<Path>) -> Result<()> {
 }
 
 pub fn write_xfbin_buf(xfbin: Xfbin) -> Result<Vec<u8>> {
-    let mut cursor = Cursor::new(Vec::new());
-
-    Ok(XfbinFile::from(xfbin) // Convert the Xfbin to an XfbinFile
-        .write_be(&mut cursor)
-        .map(|_| cursor.into_inner())?)
+    write_xfbin_buf_with(xfbin, &WriteOptions::default())
 }
 
-#[cfg(test)]
-mod tests {
+pub fn write_xfbin_buf_with(xfbin: Xfbin, options: &WriteOptions) -> Result<Vec<u8>> {
+    let encrypted = xfbin.encrypted;
 
-    use self::{
-        nucc::{NuccAnm, NuccChunkConverter, NuccStruct},
-        nucc_chunk::{nucc_chunk_anm, NuccChunk, NuccChunkAnm, NuccChunkType}, xfbin::XfbinPage,
+    // Check for the cipher before doing any work
+    let cipher = match (encrypted, options.cipher.as_ref()) {
+        (true, None) => return Err(XfbinError::MissingCipher),
+        (_, cipher) => cipher,
     };
 
-    use super::*;
-
-    #[test]
-    fn read_xfbin_test() -> Result<()> {
-        let xfbin = read_xfbin(&Path::new("4rincharsel.xfbin"))?;
-
-        for page in xfbin.pages {
-            let (page_structs, page_struct_infos, page_struct_references) = page.destructure();
-
-            for page_struct in page_structs {
-                match page_struct.chunk_type() {
-                    NuccChunkType::NuccChunkAnm => {
-                        let anm = page_struct.downcast_ref::<NuccAnm>().unwrap().clone();
-                        let boxed = Box::<dyn NuccChunk>::from(NuccChunkConverter {
-                            nucc_struct: Box::new(anm.clone()),
-                            struct_info_map: page_struct_infos.clone(),
-                            struct_reference_map: page_struct_references.clone(),
-                        });
-
-                        let anm_chunk = boxed.downcast::<NuccChunkAnm>().unwrap();
-                        let extension = anm_chunk.extension();
-                        println!("Extension: {}", extension);
-
-                        // Remove the Box from nuccChunkAnm and save it to a file
-                        let mut cursor = Cursor::new(Vec::new());
-                        NuccChunkAnm::write_boxed(anm_chunk, &mut cursor, anm.version).unwrap();
-
-                        let mut file = File::create(format!("{}{}", anm.struct_info.chunk_name, extension))?;
-
-                        file.write_all(&cursor.into_inner())?;
-
-
-                        
-                       
-                    }
-                    _ => {}
-                }
-            }
+    let mut cursor = Cursor::new(Vec::new());
 
-        }
+    XfbinFile::try_from(xfbin)? // Convert the Xfbin to an XfbinFile
+        .write_be(&mut cursor)?;
 
-        
+    let mut buf = cursor.into_inner();
 
-        Ok(())
+    if let (true, Some(cipher)) = (encrypted, cipher) {
+        cipher.encrypt(&mut buf[XFBIN_HEADER_SIZE..], 0);
     }
 
-    #[test]
-    fn write_xfbin_test() -> Result<()> {
-        let mut xfbin = read_xfbin(&Path::new("4rincharsel.xfbin"))?;
-
-        let mut new_page = XfbinPage::default();
-
-        // Create a new ANM chunk info
-        let anm_struct_info = nucc::NuccStructInfo {
-            chunk_name: String::from("4rincharsel30"),
-            filepath: String::from("c\\crsel\\4rin\\anm\\4rincharsel30.max"),
-            chunk_type: nucc_chunk::NuccChunkType::NuccChunkAnm.to_string(),
-        };
-
-        // Create a new ANM chunk with default
-        let anm_chunk = NuccAnm {
-            struct_info: anm_struct_info.clone(),
-            version: 121,
-            frame_count: 0,
-            is_looped: false,
-            clumps: vec![],
-            other_entries_indices: vec![],
-            unk_entry_indices: vec![],
-            coord_parents: vec![],
-            entries: vec![],
-        };
-
-        // Cast the ANM struct to a NuccStruct
-        let anm_struct = Box::new(anm_chunk) as Box<dyn nucc::NuccStruct>;
-
-        // Add the ANM struct to the new page
-        new_page.structs.push(anm_struct);
-        new_page.struct_infos.push(anm_struct_info.clone());
-        new_page.struct_references = xfbin.pages[0].struct_references.clone();
-
-        xfbin.pages.push(new_page);
-
-        write_xfbin(xfbin, &Path::new("4rincharsel-new.xfbin"))?;
-
-        Ok(())
-    }
+    Ok(buf)
 }
@@ -8,6 +8,7 @@ pub mod nucc_lightpoint;
 pub mod nucc_layerset;
 pub mod nucc_ambient;
 pub mod nucc_morphmodel;
+pub mod nucc_texture;
 
 pub mod nucc_unknown;
 
@@ -16,19 +17,21 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use super::error::{Result, XfbinError};
 use super::nucc_chunk::*;
 use super::xfbin_file::{XfbinChunkMap, XfbinChunkReference};
 
 pub use nucc_binary::NuccBinary;
-pub use nucc_anm::NuccAnm;
+pub use nucc_anm::{NuccAnm, NuccAnmClump};
 pub use nucc_anmstrm::NuccAnmStrm;
 pub use nucc_anmstrmframe::NuccAnmStrmFrame;
 pub use nucc_camera::NuccCamera;
 pub use nucc_lightdirc::NuccLightDirc;
 pub use nucc_lightpoint::NuccLightPoint;
-pub use nucc_layerset::NuccLayerSet;
+pub use nucc_layerset::{NuccLayerSet, NuccLayerSetLayer};
 pub use nucc_ambient::NuccAmbient;
 pub use nucc_morphmodel::NuccMorphModel;
+pub use nucc_texture::NuccTexture;
 pub use nucc_unknown::NuccUnknown;
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq, Hash)]
@@ -55,8 +58,18 @@ pub struct XfbinChunkMapConverter {
     pub filepaths: Vec<String>,
 }
 
-impl From<XfbinChunkMapConverter> for Vec<NuccStructInfo> {
-    fn from(converter: XfbinChunkMapConverter) -> Self {
+/// Looks up an entry of one of the index tables, failing instead of panicking on a bad index
+fn lookup<T: Clone>(table: &'static str, values: &[T], index: u32) -> Result<T> {
+    values
+        .get(index as usize)
+        .cloned()
+        .ok_or_else(|| XfbinError::out_of_range(table, index as usize, values.len()))
+}
+
+impl TryFrom<XfbinChunkMapConverter> for Vec<NuccStructInfo> {
+    type Error = XfbinError;
+
+    fn try_from(converter: XfbinChunkMapConverter) -> Result<Self> {
         let XfbinChunkMapConverter {
             chunk_maps,
             chunk_names: names,
@@ -66,10 +79,12 @@ impl From<XfbinChunkMapConverter> for Vec<NuccStructInfo> {
 
         chunk_maps
             .into_iter()
-            .map(|c| NuccStructInfo {
-                chunk_name: names[c.chunk_name_index as usize].clone(),
-                chunk_type: types[c.chunk_type_index as usize].clone(),
-                filepath: paths[c.filepath_index as usize].clone(),
+            .map(|c| {
+                Ok(NuccStructInfo {
+                    chunk_name: lookup("chunk name", &names, c.chunk_name_index)?,
+                    chunk_type: lookup("chunk type", &types, c.chunk_type_index)?,
+                    filepath: lookup("filepath", &paths, c.filepath_index)?,
+                })
             })
             .collect()
     }
@@ -87,8 +102,10 @@ pub struct XfbinChunkReferenceConverter {
     pub struct_infos: Vec<NuccStructInfo>,
 }
 
-impl From<XfbinChunkReferenceConverter> for Vec<NuccStructReference> {
-    fn from(converter: XfbinChunkReferenceConverter) -> Self {
+impl TryFrom<XfbinChunkReferenceConverter> for Vec<NuccStructReference> {
+    type Error = XfbinError;
+
+    fn try_from(converter: XfbinChunkReferenceConverter) -> Result<Self> {
         let XfbinChunkReferenceConverter {
             references,
             chunk_names: names,
@@ -97,9 +114,11 @@ impl From<XfbinChunkReferenceConverter> for Vec<NuccStructReference> {
 
         references
             .into_iter()
-            .map(|r| NuccStructReference {
-                chunk_name: names[r.chunk_name_index as usize].clone(),
-                struct_info: infos[r.chunk_map_index as usize].clone(),
+            .map(|r| {
+                Ok(NuccStructReference {
+                    chunk_name: lookup("chunk name", &names, r.chunk_name_index)?,
+                    struct_info: lookup("chunk map", &infos, r.chunk_map_index)?,
+                })
             })
             .collect()
     }
@@ -129,6 +148,14 @@ pub(crate) use impl_nucc_info;
 pub trait NuccStruct: NuccInfo + Downcast {
     fn chunk_type(&self) -> NuccChunkType;
     fn version(&self) -> u16;
+
+    /// Infos of the other chunks of the page that the struct refers to by chunk map index
+    ///
+    /// The writer gives each of them a chunk map, so the struct can be written to a page that
+    /// doesn't have the chunks it binds
+    fn bound_struct_infos(&self) -> Vec<&NuccStructInfo> {
+        Vec::new()
+    }
 }
 
 impl std::fmt::Debug for dyn NuccStruct {
@@ -145,11 +172,15 @@ impl std::fmt::Debug for dyn NuccStruct {
 
 impl_downcast!(NuccStruct);
 
-//// Converts a NuccStruct to a NuccChunk
-pub struct NuccStructConverter {
+/// Converts a NuccStruct to a NuccChunk
+pub struct NuccStructConverter<'a> {
     pub nucc_chunk: Box<dyn NuccChunk>,
-    pub struct_infos: Vec<NuccStructInfo>,
-    pub struct_references: Vec<NuccStructReference>,
+
+    /// Chunk maps starting at the chunk's page, so that page-local indices can be looked up directly
+    pub struct_infos: &'a [NuccStructInfo],
+
+    /// References starting at the chunk's page
+    pub struct_references: &'a [NuccStructReference],
 }
 
 pub struct NuccChunkConverter {
@@ -158,43 +189,110 @@ pub struct NuccChunkConverter {
     pub struct_reference_map: IndexMap<NuccStructReference, u32>,
 }
 
-impl From<NuccStructConverter> for Box<dyn NuccStruct> {
-    fn from(converter: NuccStructConverter) -> Self {
-        match converter.nucc_chunk.chunk_type() {
-            NuccChunkType::NuccChunkBinary => Box::new(NuccBinary::from(converter)),
-            NuccChunkType::NuccChunkAnm => Box::new(NuccAnm::from(converter)),
-            NuccChunkType::NuccChunkAnmStrm => Box::new(NuccAnmStrm::from(converter)),
-            NuccChunkType::NuccChunkAnmStrmFrame => Box::new(NuccAnmStrmFrame::from(converter)),
-            NuccChunkType::NuccChunkCamera => Box::new(NuccCamera::from(converter)),
-            NuccChunkType::NuccChunkLightDirc => Box::new(NuccLightDirc::from(converter)),
-            NuccChunkType::NuccChunkLightPoint => Box::new(NuccLightPoint::from(converter)),
-            NuccChunkType::NuccChunkLayerSet => Box::new(NuccLayerSet::from(converter)),
-            NuccChunkType::NuccChunkAmbient => Box::new(NuccAmbient::from(converter)),
-            NuccChunkType::NuccChunkMorphModel => Box::new(NuccMorphModel::from(converter)),
-            NuccChunkType::NuccChunkUnknown => Box::new(NuccUnknown::from(converter)),
-            any => panic!("Unexpected NuccChunkType: {any}"),
-        }
+/// Looks up the page-local chunk map index of a struct info
+fn struct_info_index(struct_info_map: &IndexMap<NuccStructInfo, u32>, struct_info: &NuccStructInfo) -> Result<u32> {
+    struct_info_map
+        .get(struct_info)
+        .copied()
+        .ok_or_else(|| XfbinError::UnmappedStructInfo(struct_info.clone()))
+}
+
+impl TryFrom<NuccStructConverter<'_>> for Box<dyn NuccStruct> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
+        Ok(match converter.nucc_chunk.chunk_type() {
+            NuccChunkType::NuccChunkBinary => Box::new(NuccBinary::try_from(converter)?),
+            NuccChunkType::NuccChunkAnm => Box::new(NuccAnm::try_from(converter)?),
+            NuccChunkType::NuccChunkAnmStrm => Box::new(NuccAnmStrm::try_from(converter)?),
+            NuccChunkType::NuccChunkAnmStrmFrame => Box::new(NuccAnmStrmFrame::try_from(converter)?),
+            NuccChunkType::NuccChunkCamera => Box::new(NuccCamera::try_from(converter)?),
+            NuccChunkType::NuccChunkLightDirc => Box::new(NuccLightDirc::try_from(converter)?),
+            NuccChunkType::NuccChunkLightPoint => Box::new(NuccLightPoint::try_from(converter)?),
+            NuccChunkType::NuccChunkLayerSet => Box::new(NuccLayerSet::try_from(converter)?),
+            NuccChunkType::NuccChunkAmbient => Box::new(NuccAmbient::try_from(converter)?),
+            NuccChunkType::NuccChunkMorphModel => Box::new(NuccMorphModel::try_from(converter)?),
+            NuccChunkType::NuccChunkTexture => Box::new(NuccTexture::try_from(converter)?),
+            NuccChunkType::NuccChunkUnknown => Box::new(NuccUnknown::try_from(converter)?),
+            any => return Err(XfbinError::UnexpectedChunkType(any)),
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<dyn NuccChunk> {
-    fn from(converter: NuccChunkConverter) -> Self {
-        match converter.nucc_struct.chunk_type() {
-            NuccChunkType::NuccChunkBinary => { Box::<NuccChunkBinary>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkAnm => { Box::<NuccChunkAnm>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkAnmStrm => { Box::<NuccChunkAnmStrm>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkAnmStrmFrame => { Box::<NuccChunkAnmStrmFrame>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkCamera => { Box::<NuccChunkCamera>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkLightDirc => { Box::<NuccChunkLightDirc>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkLightPoint => { Box::<NuccChunkLightPoint>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkLayerSet => { Box::<NuccChunkLayerSet>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkAmbient => { Box::<NuccChunkAmbient>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkMorphModel => { Box::<NuccChunkMorphModel>::from(converter) as Box<dyn NuccChunk> }
-            NuccChunkType::NuccChunkUnknown => { Box::<NuccChunkUnknown>::from(converter) as Box<dyn NuccChunk> }
+impl TryFrom<NuccChunkConverter> for Box<dyn NuccChunk> {
+    type Error = XfbinError;
 
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
+        Ok(match converter.nucc_struct.chunk_type() {
+            NuccChunkType::NuccChunkBinary => { Box::<NuccChunkBinary>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkAnm => { Box::<NuccChunkAnm>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkAnmStrm => { Box::<NuccChunkAnmStrm>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkAnmStrmFrame => { Box::<NuccChunkAnmStrmFrame>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkCamera => { Box::<NuccChunkCamera>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkLightDirc => { Box::<NuccChunkLightDirc>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkLightPoint => { Box::<NuccChunkLightPoint>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkLayerSet => { Box::<NuccChunkLayerSet>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkAmbient => { Box::<NuccChunkAmbient>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkMorphModel => { Box::<NuccChunkMorphModel>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkTexture => { Box::<NuccChunkTexture>::try_from(converter)? as Box<dyn NuccChunk> }
+            NuccChunkType::NuccChunkUnknown => { Box::<NuccChunkUnknown>::try_from(converter)? as Box<dyn NuccChunk> }
 
+            any => return Err(XfbinError::UnexpectedChunkType(any)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use crate::xfbin::{Xfbin, XfbinPage};
+    use crate::{read_xfbin_buf, write_xfbin_buf};
+
+    #[test]
+    fn bound_struct_infos_test() -> Result<()> {
+        let bound_structs = fixture_structs(121)
+            .into_iter()
+            .filter(|nucc_struct| !nucc_struct.bound_struct_infos().is_empty())
+            .collect::<Vec<_>>();
+
+        let chunk_types = bound_structs.iter().map(|s| s.chunk_type()).collect::<Vec<_>>();
+        assert_eq!(
+            chunk_types,
+            vec![
+                NuccChunkType::NuccChunkAnm,
+                NuccChunkType::NuccChunkAnmStrm,
+                NuccChunkType::NuccChunkLayerSet,
+                NuccChunkType::NuccChunkMorphModel,
+            ]
+        );
 
-            any => panic!("Unexpected NuccChunkType: {any}"),
+        // Each struct is moved on its own to a fresh page, without the chunks it binds
+        for nucc_struct in bound_structs {
+            let expected = nucc_struct
+                .bound_struct_infos()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let mut page = XfbinPage::default();
+            page.add_struct(nucc_struct)?;
+
+            let buf = write_xfbin_buf(Xfbin {
+                pages: vec![page],
+                ..Default::default()
+            })?;
+            let xfbin = read_xfbin_buf(buf.clone())?;
+
+            let page = &xfbin.pages[0];
+            let bound_struct_infos = page.structs[0].bound_struct_infos();
+
+            assert_eq!(bound_struct_infos, expected.iter().collect::<Vec<_>>());
+            assert!(expected.iter().all(|info| page.struct_infos.contains(info)));
+
+            assert_eq!(write_xfbin_buf(xfbin)?, buf);
         }
+
+        Ok(())
     }
 }
@@ -1,17 +1,22 @@
 use super::*;
 
+use crate::nucc_chunk::nucc_helper::Vector3;
+
 #[derive(Debug, Clone)]
 pub struct NuccAmbient {
     pub struct_info: NuccStructInfo,
     pub version: u16,
 
-    pub data: Vec<u8>,
+    pub color: Vector3,
+    pub intensity: f32,
 }
 
 impl_nucc_info!(NuccAmbient, struct_info);
 
-impl From<NuccStructConverter> for NuccAmbient {
-    fn from(converter: NuccStructConverter) -> Self {
+impl TryFrom<NuccStructConverter<'_>> for NuccAmbient {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
             struct_infos: _,
@@ -21,19 +26,21 @@ impl From<NuccStructConverter> for NuccAmbient {
         let chunk = nucc_chunk
             .downcast::<NuccChunkAmbient>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkAmbient>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
-            data: chunk.data,
-        }
+            color: chunk.color,
+            intensity: chunk.intensity,
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkAmbient> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkAmbient> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
             struct_info_map: _,
@@ -43,13 +50,13 @@ impl From<NuccChunkConverter> for Box<NuccChunkAmbient> {
         let ambient = nucc_struct
             .downcast::<NuccAmbient>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccAmbient>(c.chunk_type()))?;
 
-        Box::new(NuccChunkAmbient {
+        Ok(Box::new(NuccChunkAmbient {
             version: ambient.version,
-            data: ambient.data,
-        })
+            color: ambient.color,
+            intensity: ambient.intensity,
+        }))
     }
 }
 
@@ -1,17 +1,18 @@
 use super::*;
 
-use crate::nucc_chunk::nucc_chunk_anm::{AnmClump, AnmEntry, CoordParent};
+use crate::nucc_chunk::nucc_chunk_anm::{AnmClump, AnmEntry, CoordParent, DEFAULT_FRAME_SIZE};
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct NuccAnm {
     pub struct_info: NuccStructInfo,
 
     pub version: u16,
 
     pub frame_count: u32,
+    pub frame_size: u32,
     pub is_looped: bool,
 
-    pub clumps: Vec<AnmClump>,
+    pub clumps: Vec<NuccAnmClump>,
 
     pub other_entries_indices: Vec<u32>,
     pub unk_entry_indices: Vec<u32>,
@@ -23,77 +24,150 @@ pub struct NuccAnm {
 
 impl_nucc_info!(NuccAnm, struct_info);
 
-impl From<NuccStructConverter> for NuccAnm {
-    fn from(converter: NuccStructConverter) -> Self {
+impl Default for NuccAnm {
+    fn default() -> Self {
+        Self {
+            struct_info: Default::default(),
+            version: 0,
+            frame_count: 0,
+            frame_size: DEFAULT_FRAME_SIZE,
+            is_looped: false,
+            clumps: Vec::new(),
+            other_entries_indices: Vec::new(),
+            unk_entry_indices: Vec::new(),
+            coord_parents: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// A clump of an animation, with its page-local chunk map indices resolved
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NuccAnmClump {
+    pub clump: NuccStructInfo,
+    pub bone_materials: Vec<NuccStructInfo>,
+    pub models: Vec<NuccStructInfo>,
+}
+
+impl NuccAnmClump {
+    pub(crate) fn resolve(
+        clump_index: u32,
+        bone_material_indices: &[u32],
+        model_indices: &[u32],
+        struct_infos: &[NuccStructInfo],
+    ) -> Result<Self> {
+        let resolve_all = |indices: &[u32]| {
+            indices
+                .iter()
+                .map(|&index| lookup("chunk map index", struct_infos, index))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok(Self {
+            clump: lookup("chunk map index", struct_infos, clump_index)?,
+            bone_materials: resolve_all(bone_material_indices)?,
+            models: resolve_all(model_indices)?,
+        })
+    }
+
+    /// The clump, bone materials and models, in that order
+    pub(crate) fn struct_infos(&self) -> impl Iterator<Item = &NuccStructInfo> {
+        std::iter::once(&self.clump)
+            .chain(self.bone_materials.iter())
+            .chain(self.models.iter())
+    }
+
+    /// Indices of the clump, bone materials and models in the rebuilt chunk maps of the page
+    pub(crate) fn indices(&self, struct_info_map: &IndexMap<NuccStructInfo, u32>) -> Result<(u32, Vec<u32>, Vec<u32>)> {
+        let index_all = |struct_infos: &[NuccStructInfo]| {
+            struct_infos
+                .iter()
+                .map(|struct_info| struct_info_index(struct_info_map, struct_info))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok((
+            struct_info_index(struct_info_map, &self.clump)?,
+            index_all(&self.bone_materials)?,
+            index_all(&self.models)?,
+        ))
+    }
+}
+
+impl TryFrom<NuccStructConverter<'_>> for NuccAnm {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
-            struct_infos: _,
+            struct_infos,
             struct_references: _,
         } = converter;
 
         let chunk = nucc_chunk
             .downcast::<NuccChunkAnm>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkAnm>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
             frame_count: chunk.frame_count,
+            frame_size: chunk.frame_size,
             is_looped: chunk.is_looped == 1,
-            clumps: chunk.clumps,
+            clumps: chunk
+                .clumps
+                .iter()
+                .map(|c| NuccAnmClump::resolve(c.clump_index, &c.bone_material_indices, &c.model_indices, struct_infos))
+                .collect::<Result<_>>()?,
             other_entries_indices: chunk.other_entries_indices,
             unk_entry_indices: chunk.unk_entry_indices,
             coord_parents: chunk.coord_parents,
             entries: chunk.entries,
-        }
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkAnm> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkAnm> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
-            struct_info_map: _,
+            struct_info_map,
             struct_reference_map: _,
         } = converter;
 
-        let mut anm = nucc_struct.downcast::<NuccAnm>().map(|s| *s).ok().unwrap();
-
-
-        // Apply the pad values method to the entries
-        for entry in anm.entries.iter_mut() {
-            for (curve, curve_header) in entry
-            .curves
-            .iter_mut()
-            .zip(&mut entry.curve_headers)
-            {
-                
-                if curve.get_curve_format() == nucc_chunk_anm::AnmCurveFormat::SHORT3 as u16
-                    || curve.get_curve_format() == nucc_chunk_anm::AnmCurveFormat::SHORT1_ALT as u16
-                    || curve.get_curve_format() == nucc_chunk_anm::AnmCurveFormat::SHORT1 as u16
-                    || curve.get_curve_format() == nucc_chunk_anm::AnmCurveFormat::BYTE3 as u16
-                {
-                    curve.pad_values();
-                }
-
-                curve_header.frame_count = curve.get_frame_count() as u16;
-            }
-            
-        }
-        
-        let mut chunk = NuccChunkAnm::default();
-        chunk.version = anm.version;
-        chunk.frame_count = anm.frame_count;
-        chunk.is_looped = if anm.is_looped { 1 } else { 0 };
-        chunk.clumps = anm.clumps;
-        chunk.other_entries_indices = anm.other_entries_indices;
-        chunk.unk_entry_indices = anm.unk_entry_indices;
-        chunk.coord_parents = anm.coord_parents;
-        chunk.entries = anm.entries;
-
-        Box::new(chunk)
+        let anm = nucc_struct
+            .downcast::<NuccAnm>()
+            .map(|s| *s)
+            .map_err(|s| XfbinError::downcast_mismatch::<NuccAnm>(s.chunk_type()))?;
+
+        let chunk = NuccChunkAnm {
+            version: anm.version,
+            frame_count: anm.frame_count,
+            frame_size: anm.frame_size,
+            is_looped: if anm.is_looped { 1 } else { 0 },
+            clumps: anm
+                .clumps
+                .iter()
+                .map(|c| {
+                    let (clump_index, bone_material_indices, model_indices) = c.indices(&struct_info_map)?;
+
+                    Ok(AnmClump {
+                        clump_index,
+                        bone_material_indices,
+                        model_indices,
+                    })
+                })
+                .collect::<Result<_>>()?,
+            other_entries_indices: anm.other_entries_indices,
+            unk_entry_indices: anm.unk_entry_indices,
+            coord_parents: anm.coord_parents,
+            entries: anm.entries,
+        };
+
+        Ok(Box::new(chunk))
     }
 }
 
@@ -105,4 +179,74 @@ impl NuccStruct for NuccAnm {
     fn version(&self) -> u16 {
         self.version
     }
+
+    fn bound_struct_infos(&self) -> Vec<&NuccStructInfo> {
+        self.clumps.iter().flat_map(NuccAnmClump::struct_infos).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc_chunk::NuccChunkAnm;
+    use crate::reader::XfbinReader;
+    use crate::test_util::*;
+    use crate::xfbin::Xfbin;
+    use crate::{read_xfbin_buf, write_xfbin_buf};
+    use std::io::Cursor;
+
+    /// The raw clumps of the first anm chunk in `buf`
+    fn raw_anm_clumps(buf: Vec<u8>) -> Result<Vec<AnmClump>> {
+        let mut reader = XfbinReader::new(Cursor::new(buf))?;
+        let descriptor = reader
+            .struct_chunks()
+            .find(|d| d.chunk_type() == NuccChunkType::NuccChunkAnm)
+            .cloned()
+            .unwrap();
+
+        let chunk = reader.read_chunk(&descriptor)?;
+
+        Ok(chunk.downcast_ref::<NuccChunkAnm>().unwrap().clumps.clone())
+    }
+
+    #[test]
+    fn anm_clump_indices_test() -> Result<()> {
+        let buf = write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        })?;
+
+        let raw_clumps = raw_anm_clumps(buf.clone())?;
+        let mut xfbin = read_xfbin_buf(buf)?;
+
+        let expected = fixture_structs(121)[1].downcast_ref::<NuccAnm>().unwrap().clumps.clone();
+        let anm = xfbin.pages[0].structs[1].downcast_ref::<NuccAnm>().unwrap();
+        assert_eq!(anm.clumps, expected);
+
+        let anmstrm = xfbin.pages[0].structs[2].downcast_ref::<NuccAnmStrm>().unwrap();
+        assert_eq!(anmstrm.clumps[0].models[0], clump_info());
+
+        // Reordering the chunk maps of the page changes the indices, but not what they point to
+        xfbin.pages[0].struct_infos.reverse();
+
+        let buf = write_xfbin_buf(xfbin)?;
+        assert_ne!(raw_anm_clumps(buf.clone())?, raw_clumps);
+
+        let xfbin = read_xfbin_buf(buf)?;
+        let anm = xfbin.pages[0].structs[1].downcast_ref::<NuccAnm>().unwrap();
+        assert_eq!(anm.clumps, expected);
+
+        // Clumps that aren't in the page still get a chunk map to point to
+        let mut structs = fixture_structs(121);
+        structs[1].downcast_mut::<NuccAnm>().unwrap().clumps[0].clump.chunk_name = String::from("missing");
+
+        let xfbin = read_xfbin_buf(write_xfbin_buf(Xfbin {
+            pages: vec![page(structs)],
+            ..Default::default()
+        })?)?;
+        let anm = xfbin.pages[0].structs[1].downcast_ref::<NuccAnm>().unwrap();
+        assert_eq!(anm.clumps[0].clump.chunk_name, "missing");
+
+        Ok(())
+    }
 }
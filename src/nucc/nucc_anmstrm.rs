@@ -1,20 +1,22 @@
 use super::*;
 
-use crate::nucc_chunk::nucc_chunk_anm::CoordParent;
+use super::nucc_anm::NuccAnmClump;
+use crate::nucc_chunk::nucc_chunk_anm::{CoordParent, DEFAULT_FRAME_SIZE};
 use crate::nucc_chunk::nucc_chunk_anmstrm::{AnmStrmClump, AnmStrmFrameInfo};
 
-#[derive(Default)]
 pub struct NuccAnmStrm {
     pub struct_info: NuccStructInfo,
 
     pub version: u16,
 
     pub frame_count: u32,
+    pub frame_size: u32,
     pub is_looped: bool,
 
-    pub clumps: Vec<AnmStrmClump>,
+    pub clumps: Vec<NuccAnmClump>,
 
     pub other_entry_indices: Vec<u32>,
+    pub unk_entry_count: u16,
 
     pub coord_parents: Vec<CoordParent>,
 
@@ -23,59 +25,97 @@ pub struct NuccAnmStrm {
 
 impl_nucc_info!(NuccAnmStrm, struct_info);
 
-impl From<NuccStructConverter> for NuccAnmStrm {
-    fn from(converter: NuccStructConverter) -> Self {
+impl Default for NuccAnmStrm {
+    fn default() -> Self {
+        Self {
+            struct_info: Default::default(),
+            version: 0,
+            frame_count: 0,
+            frame_size: DEFAULT_FRAME_SIZE,
+            is_looped: false,
+            clumps: Vec::new(),
+            other_entry_indices: Vec::new(),
+            unk_entry_count: 0,
+            coord_parents: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl TryFrom<NuccStructConverter<'_>> for NuccAnmStrm {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
-            struct_infos: _,
+            struct_infos,
             struct_references: _,
         } = converter;
 
         let chunk = nucc_chunk
             .downcast::<NuccChunkAnmStrm>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkAnmStrm>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
             frame_count: chunk.frame_count,
+            frame_size: chunk.frame_size,
             is_looped: chunk.is_looped == 1,
-            clumps: chunk.clumps,
+            clumps: chunk
+                .clumps
+                .iter()
+                .map(|c| NuccAnmClump::resolve(c.clump_index, &c.bone_material_indices, &c.model_indices, struct_infos))
+                .collect::<Result<_>>()?,
             other_entry_indices: chunk.other_entry_indices,
+            unk_entry_count: chunk.unk_entry_count,
             coord_parents: chunk.coord_parents,
             entries: chunk.entries,
-        }
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkAnmStrm> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkAnmStrm> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
-            struct_info_map: _,
+            struct_info_map,
             struct_reference_map: _,
         } = converter;
 
         let anmstrm = nucc_struct
             .downcast::<NuccAnmStrm>()
             .map(|a| *a)
-            .ok()
-            .unwrap();
+            .map_err(|a| XfbinError::downcast_mismatch::<NuccAnmStrm>(a.chunk_type()))?;
 
         let chunk = NuccChunkAnmStrm {
             version: anmstrm.version,
             frame_count: anmstrm.frame_count,
+            frame_size: anmstrm.frame_size,
             is_looped: if anmstrm.is_looped { 1 } else { 0 },
-            clumps: anmstrm.clumps,
-            unk_entry_count: 0,
+            clumps: anmstrm
+                .clumps
+                .iter()
+                .map(|c| {
+                    let (clump_index, bone_material_indices, model_indices) = c.indices(&struct_info_map)?;
+
+                    Ok(AnmStrmClump {
+                        clump_index,
+                        bone_material_indices,
+                        model_indices,
+                    })
+                })
+                .collect::<Result<_>>()?,
+            unk_entry_count: anmstrm.unk_entry_count,
             other_entry_indices: anmstrm.other_entry_indices,
             coord_parents: anmstrm.coord_parents,
             entries: anmstrm.entries,
         };
 
-        Box::new(chunk)
+        Ok(Box::new(chunk))
     }
 }
 
@@ -87,4 +127,8 @@ impl NuccStruct for NuccAnmStrm {
     fn version(&self) -> u16 {
         self.version
     }
+
+    fn bound_struct_infos(&self) -> Vec<&NuccStructInfo> {
+        self.clumps.iter().flat_map(NuccAnmClump::struct_infos).collect()
+    }
 }
@@ -13,8 +13,10 @@ pub struct NuccAnmStrmFrame {
 
 impl_nucc_info!(NuccAnmStrmFrame, struct_info);
 
-impl From<NuccStructConverter> for NuccAnmStrmFrame {
-    fn from(converter: NuccStructConverter) -> Self {
+impl TryFrom<NuccStructConverter<'_>> for NuccAnmStrmFrame {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
             struct_infos: _,
@@ -24,21 +26,22 @@ impl From<NuccStructConverter> for NuccAnmStrmFrame {
         let chunk = nucc_chunk
             .downcast::<NuccChunkAnmStrmFrame>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkAnmStrmFrame>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
             frame_number: chunk.frame_number,
             unknown: chunk.unknown,
             entries: chunk.entries,
-        }
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkAnmStrmFrame> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkAnmStrmFrame> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
             struct_info_map: _,
@@ -48,8 +51,7 @@ impl From<NuccChunkConverter> for Box<NuccChunkAnmStrmFrame> {
         let anmstrmframe = nucc_struct
             .downcast::<NuccAnmStrmFrame>()
             .map(|a| *a)
-            .ok()
-            .unwrap();
+            .map_err(|a| XfbinError::downcast_mismatch::<NuccAnmStrmFrame>(a.chunk_type()))?;
 
         let chunk = NuccChunkAnmStrmFrame {
             version: anmstrmframe.version,
@@ -58,7 +60,7 @@ impl From<NuccChunkConverter> for Box<NuccChunkAnmStrmFrame> {
             entries: anmstrmframe.entries,
         };
 
-        Box::new(chunk)
+        Ok(Box::new(chunk))
     }
 }
 
@@ -9,8 +9,10 @@ pub struct NuccBinary {
 
 impl_nucc_info!(NuccBinary, struct_info);
 
-impl From<NuccStructConverter> for NuccBinary {
-    fn from(converter: NuccStructConverter) -> Self {
+impl TryFrom<NuccStructConverter<'_>> for NuccBinary {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
             struct_infos: _,
@@ -20,19 +22,20 @@ impl From<NuccStructConverter> for NuccBinary {
         let chunk = nucc_chunk
             .downcast::<NuccChunkBinary>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkBinary>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
             data: chunk.data,
-        }
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkBinary> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkBinary> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
             struct_info_map: _,
@@ -42,13 +45,12 @@ impl From<NuccChunkConverter> for Box<NuccChunkBinary> {
         let binary = nucc_struct
             .downcast::<NuccBinary>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccBinary>(c.chunk_type()))?;
 
-        Box::new(NuccChunkBinary {
+        Ok(Box::new(NuccChunkBinary {
             version: binary.version,
             data: binary.data,
-        })
+        }))
     }
 }
 
@@ -5,13 +5,17 @@ pub struct NuccCamera {
     pub struct_info: NuccStructInfo,
     pub version: u16,
 
-    pub fov: f32
+    pub field00: u32,
+    pub fov: f32,
+    pub extra: Vec<u8>,
 }
 
 impl_nucc_info!(NuccCamera, struct_info);
 
-impl From<NuccStructConverter> for NuccCamera {
-    fn from(converter: NuccStructConverter) -> Self {
+impl TryFrom<NuccStructConverter<'_>> for NuccCamera {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
             struct_infos: _,
@@ -21,19 +25,22 @@ impl From<NuccStructConverter> for NuccCamera {
         let chunk = nucc_chunk
             .downcast::<NuccChunkCamera>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkCamera>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
+            field00: chunk.field00,
             fov: chunk.fov,
-        }
+            extra: chunk.extra,
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkCamera> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkCamera> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
             struct_info_map: _,
@@ -43,13 +50,14 @@ impl From<NuccChunkConverter> for Box<NuccChunkCamera> {
         let cam = nucc_struct
             .downcast::<NuccCamera>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccCamera>(c.chunk_type()))?;
 
-        Box::new(NuccChunkCamera {
+        Ok(Box::new(NuccChunkCamera {
             version: cam.version,
+            field00: cam.field00,
             fov: cam.fov,
-        })
+            extra: cam.extra,
+        }))
     }
 }
 
@@ -1,55 +1,119 @@
 use super::*;
 
+use crate::nucc_chunk::nucc_chunk_layerset::LayerSetLayer;
+
 #[derive(Debug, Clone)]
 pub struct NuccLayerSet {
     pub struct_info: NuccStructInfo,
     pub version: u16,
 
-    pub data: Vec<u8>,
+    pub field00: u16,
+    pub layers: Vec<NuccLayerSetLayer>,
+    pub extra: Vec<u8>,
 }
 
 impl_nucc_info!(NuccLayerSet, struct_info);
 
-impl From<NuccStructConverter> for NuccLayerSet {
-    fn from(converter: NuccStructConverter) -> Self {
+/// A layer of a layer set, with its page-local chunk map index resolved
+///
+/// Layers are read until the end of the chunk, so trailing data can give indices that aren't in
+/// the page's chunk maps. Those layers have no `chunk` and keep their raw `chunk_index` instead.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NuccLayerSetLayer {
+    pub chunk: Option<NuccStructInfo>,
+
+    /// Index as read when it isn't in the page's chunk maps, and 0 otherwise
+    ///
+    /// Only written back when `chunk` is `None`, as the index of a resolved chunk depends on the page
+    pub chunk_index: u32,
+    pub flags: u32,
+
+    pub field08: u32,
+    pub field0c: u32,
+}
+
+impl NuccLayerSetLayer {
+    pub(crate) fn resolve(layer: &LayerSetLayer, struct_infos: &[NuccStructInfo]) -> Self {
+        let chunk = struct_infos.get(layer.chunk_index as usize).cloned();
+
+        Self {
+            chunk_index: if chunk.is_none() { layer.chunk_index } else { 0 },
+            chunk,
+            flags: layer.flags,
+            field08: layer.field08,
+            field0c: layer.field0c,
+        }
+    }
+
+    pub(crate) fn layer(&self, struct_info_map: &IndexMap<NuccStructInfo, u32>) -> Result<LayerSetLayer> {
+        let chunk_index = match &self.chunk {
+            Some(chunk) => struct_info_index(struct_info_map, chunk)?,
+            None => self.chunk_index,
+        };
+
+        Ok(LayerSetLayer {
+            chunk_index,
+            flags: self.flags,
+            field08: self.field08,
+            field0c: self.field0c,
+        })
+    }
+}
+
+impl TryFrom<NuccStructConverter<'_>> for NuccLayerSet {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
-            struct_infos: _,
+            struct_infos,
             struct_references: _,
         } = converter;
 
         let chunk = nucc_chunk
             .downcast::<NuccChunkLayerSet>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkLayerSet>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
-            data: chunk.data,
-        }
+            field00: chunk.field00,
+            layers: chunk
+                .layers
+                .iter()
+                .map(|layer| NuccLayerSetLayer::resolve(layer, struct_infos))
+                .collect(),
+            extra: chunk.extra,
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkLayerSet> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkLayerSet> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
-            struct_info_map: _,
+            struct_info_map,
             struct_reference_map: _,
         } = converter;
 
         let layerset = nucc_struct
             .downcast::<NuccLayerSet>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccLayerSet>(c.chunk_type()))?;
 
-        Box::new(NuccChunkLayerSet {
+        Ok(Box::new(NuccChunkLayerSet {
             version: layerset.version,
-            data: layerset.data,
-        })
+            field00: layerset.field00,
+            layers: layerset
+                .layers
+                .iter()
+                .map(|layer| layer.layer(&struct_info_map))
+                .collect::<Result<_>>()?,
+            extra: layerset.extra,
+        }))
     }
 }
 
@@ -61,4 +125,111 @@ impl NuccStruct for NuccLayerSet {
     fn version(&self) -> u16 {
         self.version
     }
+
+    fn bound_struct_infos(&self) -> Vec<&NuccStructInfo> {
+        self.layers.iter().filter_map(|layer| layer.chunk.as_ref()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::XfbinReader;
+    use crate::test_util::*;
+    use crate::xfbin::Xfbin;
+    use crate::{read_xfbin_buf, write_xfbin_buf};
+    use std::io::Cursor;
+
+    #[test]
+    fn layerset_chunk_indices_test() -> Result<()> {
+        let layerset = |xfbin: &Xfbin| {
+            xfbin.pages[0]
+                .structs
+                .iter()
+                .find_map(|s| s.downcast_ref::<NuccLayerSet>())
+                .unwrap()
+                .layers
+                .clone()
+        };
+
+        let expected = layerset(&Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        });
+
+        let mut xfbin = read_xfbin_buf(write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        })?)?;
+        assert_eq!(layerset(&xfbin), expected);
+
+        // The layers are written with the indices of their chunks in the reordered chunk maps
+        xfbin.pages[0].struct_infos.reverse();
+
+        let buf = write_xfbin_buf(xfbin)?;
+        let xfbin = read_xfbin_buf(buf.clone())?;
+        let struct_infos = &xfbin.pages[0].struct_infos;
+
+        let mut reader = XfbinReader::new(Cursor::new(buf.clone()))?;
+        let descriptor = reader
+            .struct_chunks()
+            .find(|d| d.chunk_type() == NuccChunkType::NuccChunkLayerSet)
+            .cloned()
+            .unwrap();
+        let chunk = reader.read_chunk(&descriptor)?;
+
+        let chunk_indices = chunk
+            .downcast_ref::<NuccChunkLayerSet>()
+            .unwrap()
+            .layers
+            .iter()
+            .map(|layer| layer.chunk_index as usize)
+            .collect::<Vec<_>>();
+        let chunks = expected
+            .iter()
+            .map(|layer| struct_infos.iter().position(|info| Some(info) == layer.chunk.as_ref()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(chunk_indices, chunks);
+
+        assert_eq!(layerset(&xfbin), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn layerset_unmapped_index_test() -> Result<()> {
+        let mut structs = fixture_structs(121);
+        let layerset = structs
+            .iter_mut()
+            .find_map(|s| s.downcast_mut::<NuccLayerSet>())
+            .unwrap();
+
+        // Out of range indices are kept raw instead of failing a strict read
+        layerset.layers.push(NuccLayerSetLayer {
+            chunk: None,
+            chunk_index: 0xFFFF,
+            flags: 4,
+            ..Default::default()
+        });
+        let expected = layerset.layers.clone();
+
+        let buf = write_xfbin_buf(Xfbin {
+            pages: vec![page(structs)],
+            ..Default::default()
+        })?;
+        let xfbin = read_xfbin_buf(buf.clone())?;
+
+        let layers = &xfbin.pages[0]
+            .structs
+            .iter()
+            .find_map(|s| s.downcast_ref::<NuccLayerSet>())
+            .unwrap()
+            .layers;
+
+        assert_eq!(*layers, expected);
+
+        assert_eq!(write_xfbin_buf(xfbin)?, buf);
+
+        Ok(())
+    }
 }
@@ -1,17 +1,25 @@
 use super::*;
 
+use crate::nucc_chunk::nucc_helper::{Vector3, Vector4};
+
 #[derive(Debug, Clone)]
 pub struct NuccLightDirc {
     pub struct_info: NuccStructInfo,
     pub version: u16,
 
-    pub data: Vec<u8>,
+    pub color: Vector3,
+    pub intensity: f32,
+    pub direction: Vector4,
+    pub flags: u32,
+    pub unk: [u8; 28],
 }
 
 impl_nucc_info!(NuccLightDirc, struct_info);
 
-impl From<NuccStructConverter> for NuccLightDirc {
-    fn from(converter: NuccStructConverter) -> Self {
+impl TryFrom<NuccStructConverter<'_>> for NuccLightDirc {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
             struct_infos: _,
@@ -21,19 +29,24 @@ impl From<NuccStructConverter> for NuccLightDirc {
         let chunk = nucc_chunk
             .downcast::<NuccChunkLightDirc>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkLightDirc>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
-            data: chunk.data,
-        }
+            color: chunk.color,
+            intensity: chunk.intensity,
+            direction: chunk.direction,
+            flags: chunk.flags,
+            unk: chunk.unk,
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkLightDirc> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkLightDirc> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
             struct_info_map: _,
@@ -43,13 +56,16 @@ impl From<NuccChunkConverter> for Box<NuccChunkLightDirc> {
         let lightdirc = nucc_struct
             .downcast::<NuccLightDirc>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccLightDirc>(c.chunk_type()))?;
 
-        Box::new(NuccChunkLightDirc {
+        Ok(Box::new(NuccChunkLightDirc {
             version: lightdirc.version,
-            data: lightdirc.data,
-        })
+            color: lightdirc.color,
+            intensity: lightdirc.intensity,
+            direction: lightdirc.direction,
+            flags: lightdirc.flags,
+            unk: lightdirc.unk,
+        }))
     }
 }
 
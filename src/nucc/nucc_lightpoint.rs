@@ -1,17 +1,27 @@
 use super::*;
 
+use crate::nucc_chunk::nucc_helper::Vector3;
+
 #[derive(Debug, Clone)]
 pub struct NuccLightPoint {
     pub struct_info: NuccStructInfo,
     pub version: u16,
 
-    pub data: Vec<u8>,
+    pub color: Vector3,
+    pub position: Vector3,
+    pub intensity: f32,
+    pub radius: f32,
+    pub falloff: f32,
+    pub flags: u32,
+    pub unk: [u8; 24],
 }
 
 impl_nucc_info!(NuccLightPoint, struct_info);
 
-impl From<NuccStructConverter> for NuccLightPoint {
-    fn from(converter: NuccStructConverter) -> Self {
+impl TryFrom<NuccStructConverter<'_>> for NuccLightPoint {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
             struct_infos: _,
@@ -21,19 +31,26 @@ impl From<NuccStructConverter> for NuccLightPoint {
         let chunk = nucc_chunk
             .downcast::<NuccChunkLightPoint>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkLightPoint>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
-            data: chunk.data,
-        }
+            color: chunk.color,
+            position: chunk.position,
+            intensity: chunk.intensity,
+            radius: chunk.radius,
+            falloff: chunk.falloff,
+            flags: chunk.flags,
+            unk: chunk.unk,
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkLightPoint> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkLightPoint> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
             struct_info_map: _,
@@ -43,13 +60,18 @@ impl From<NuccChunkConverter> for Box<NuccChunkLightPoint> {
         let lightpoint = nucc_struct
             .downcast::<NuccLightPoint>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccLightPoint>(c.chunk_type()))?;
 
-        Box::new(NuccChunkLightPoint {
+        Ok(Box::new(NuccChunkLightPoint {
             version: lightpoint.version,
-            data: lightpoint.data,
-        })
+            color: lightpoint.color,
+            position: lightpoint.position,
+            intensity: lightpoint.intensity,
+            radius: lightpoint.radius,
+            falloff: lightpoint.falloff,
+            flags: lightpoint.flags,
+            unk: lightpoint.unk,
+        }))
     }
 }
 
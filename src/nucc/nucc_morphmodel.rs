@@ -1,55 +1,111 @@
 use super::*;
 
+use crate::nucc_chunk::nucc_chunk_anmstrmframe::AnmEntryMorphModel;
+
 #[derive(Debug, Clone)]
 pub struct NuccMorphModel {
     pub struct_info: NuccStructInfo,
     pub version: u16,
 
-    pub data: Vec<u8>,
+    pub flags: u32,
+
+    pub clump: NuccStructInfo,
+    pub model: NuccStructInfo,
+
+    /// The models that are morphed between, which have to be consecutive references of the page
+    pub targets: Vec<NuccStructReference>,
+
+    /// Reference index that's written when there are no targets, otherwise it's the first target's
+    pub target_reference_index: u32,
+
+    pub field14: u32,
+    pub field18: u32,
 }
 
 impl_nucc_info!(NuccMorphModel, struct_info);
 
-impl From<NuccStructConverter> for NuccMorphModel {
-    fn from(converter: NuccStructConverter) -> Self {
+impl NuccMorphModel {
+    /// Pairs the morph weights of an animation entry with the names of the targets they apply to
+    pub fn target_weights<'a>(&'a self, entry: &'a AnmEntryMorphModel) -> impl Iterator<Item = (&'a str, f32)> {
+        self.targets
+            .iter()
+            .zip(entry.morph_weight.iter())
+            .map(|(target, &weight)| (target.chunk_name.as_str(), weight))
+    }
+}
+
+impl TryFrom<NuccStructConverter<'_>> for NuccMorphModel {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
-            struct_infos: _,
-            struct_references: _,
+            struct_infos,
+            struct_references,
         } = converter;
 
         let chunk = nucc_chunk
             .downcast::<NuccChunkMorphModel>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkMorphModel>(c.chunk_type()))?;
 
-        Self {
+        let targets = (0..chunk.target_count)
+            .map(|i| lookup("chunk reference", struct_references, chunk.target_reference_index + i))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
             struct_info: Default::default(),
             version: chunk.version,
-            data: chunk.data,
-        }
+            flags: chunk.flags,
+            clump: lookup("chunk map index", struct_infos, chunk.clump_index)?,
+            model: lookup("chunk map index", struct_infos, chunk.model_index)?,
+            targets,
+            target_reference_index: chunk.target_reference_index,
+            field14: chunk.field14,
+            field18: chunk.field18,
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkMorphModel> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkMorphModel> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
-            struct_info_map: _,
-            struct_reference_map: _,
+            struct_info_map,
+            struct_reference_map,
         } = converter;
 
         let morphmodel = nucc_struct
             .downcast::<NuccMorphModel>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccMorphModel>(c.chunk_type()))?;
 
-        Box::new(NuccChunkMorphModel {
+        let target_reference_index = match morphmodel.targets.first() {
+            Some(target) => *struct_reference_map
+                .get(target)
+                .ok_or_else(|| XfbinError::UnmappedReference(target.chunk_name.clone()))?,
+            None => morphmodel.target_reference_index,
+        };
+
+        // The chunk only stores the first reference, so the rest have to follow it
+        for (i, target) in morphmodel.targets.iter().enumerate() {
+            if Some(target_reference_index + i as u32) != struct_reference_map.get(target).copied() {
+                return Err(XfbinError::UnmappedReference(target.chunk_name.clone()));
+            }
+        }
+
+        Ok(Box::new(NuccChunkMorphModel {
             version: morphmodel.version,
-            data: morphmodel.data,
-        })
+            flags: morphmodel.flags,
+            clump_index: struct_info_index(&struct_info_map, &morphmodel.clump)?,
+            model_index: struct_info_index(&struct_info_map, &morphmodel.model)?,
+            target_count: morphmodel.targets.len() as u32,
+            target_reference_index,
+            field14: morphmodel.field14,
+            field18: morphmodel.field18,
+        }))
     }
 }
 
@@ -61,4 +117,109 @@ impl NuccStruct for NuccMorphModel {
     fn version(&self) -> u16 {
         self.version
     }
+
+    fn bound_struct_infos(&self) -> Vec<&NuccStructInfo> {
+        vec![&self.clump, &self.model]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc_chunk::NuccChunkMorphModel;
+    use crate::reader::XfbinReader;
+    use crate::test_util::*;
+    use crate::xfbin::{Xfbin, XfbinPage};
+    use crate::{read_xfbin_buf, write_xfbin_buf};
+    use std::io::Cursor;
+
+    fn morphmodel_page(targets: &[&str]) -> XfbinPage {
+        let binary = |name: &str| -> Box<dyn NuccStruct> {
+            Box::new(NuccBinary {
+                struct_info: struct_info(name, NuccChunkType::NuccChunkBinary),
+                version: 121,
+                data: bytes(4),
+            })
+        };
+
+        let reference = |name: &str| NuccStructReference {
+            chunk_name: String::from(name),
+            struct_info: struct_info(&format!("{}_model", name), NuccChunkType::NuccChunkBinary),
+        };
+
+        XfbinPage {
+            structs: vec![
+                binary("base"),
+                binary("smile_model"),
+                binary("blink_model"),
+                Box::new(NuccMorphModel {
+                    struct_info: struct_info("morphmodel", NuccChunkType::NuccChunkMorphModel),
+                    version: 121,
+                    flags: 0,
+                    clump: clump_info(),
+                    model: struct_info("base", NuccChunkType::NuccChunkBinary),
+                    targets: targets.iter().map(|&name| reference(name)).collect(),
+                    target_reference_index: 0,
+                    field14: 0,
+                    field18: 0,
+                }),
+            ],
+            struct_infos: vec![clump_info()],
+            struct_references: vec![reference("unused"), reference("smile"), reference("blink")],
+        }
+    }
+
+    #[test]
+    fn morphmodel_references_test() -> Result<()> {
+        // The morph model's indices are local to the second page
+        let buf = write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121)), morphmodel_page(&["smile", "blink"])],
+            ..Default::default()
+        })?;
+
+        let xfbin = read_xfbin_buf(buf.clone())?;
+        let morphmodel = xfbin.pages[1].structs[3].downcast_ref::<NuccMorphModel>().unwrap();
+
+        assert_eq!(morphmodel.clump, clump_info());
+        assert_eq!(morphmodel.model, struct_info("base", NuccChunkType::NuccChunkBinary));
+        assert_eq!(
+            morphmodel.targets.iter().map(|t| t.chunk_name.as_str()).collect::<Vec<_>>(),
+            vec!["smile", "blink"]
+        );
+
+        let entry = AnmEntryMorphModel {
+            frame_count: 2,
+            morph_weight: vec![0.25, 0.75],
+        };
+        assert_eq!(
+            morphmodel.target_weights(&entry).collect::<Vec<_>>(),
+            vec![("smile", 0.25), ("blink", 0.75)]
+        );
+
+        let mut reader = XfbinReader::new(Cursor::new(buf))?;
+        let descriptor = reader
+            .struct_chunks()
+            .find(|d| d.page_index == 1 && d.chunk_type() == NuccChunkType::NuccChunkMorphModel)
+            .cloned()
+            .unwrap();
+
+        let chunk = reader.read_chunk(&descriptor)?;
+        let chunk = chunk.downcast_ref::<NuccChunkMorphModel>().unwrap();
+        assert_eq!((chunk.target_count, chunk.target_reference_index), (2, 1));
+
+        let nucc_struct = reader.read_struct(&descriptor)?;
+        assert_eq!(
+            nucc_struct.downcast_ref::<NuccMorphModel>().unwrap().targets,
+            morphmodel.targets
+        );
+
+        // Targets can only be written if they're consecutive references
+        let result = write_xfbin_buf(Xfbin {
+            pages: vec![morphmodel_page(&["blink", "smile"])],
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(XfbinError::UnmappedReference(name)) if name == "smile"));
+
+        Ok(())
+    }
 }
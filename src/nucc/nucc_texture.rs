@@ -0,0 +1,82 @@
+use super::*;
+
+use crate::nucc_chunk::nucc_chunk_texture::Nut;
+
+#[derive(Debug, Clone)]
+pub struct NuccTexture {
+    pub struct_info: NuccStructInfo,
+    pub version: u16,
+
+    pub field00: u16,
+    pub width: u16,
+    pub height: u16,
+    pub field06: u16,
+
+    /// NTP3 container with one or more textures
+    pub nut: Nut,
+}
+
+impl_nucc_info!(NuccTexture, struct_info);
+
+impl TryFrom<NuccStructConverter<'_>> for NuccTexture {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
+        let NuccStructConverter {
+            nucc_chunk,
+            struct_infos: _,
+            struct_references: _,
+        } = converter;
+
+        let chunk = nucc_chunk
+            .downcast::<NuccChunkTexture>()
+            .map(|c| *c)
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkTexture>(c.chunk_type()))?;
+
+        Ok(Self {
+            struct_info: Default::default(),
+            version: chunk.version,
+            field00: chunk.field00,
+            width: chunk.width,
+            height: chunk.height,
+            field06: chunk.field06,
+            nut: chunk.nut,
+        })
+    }
+}
+
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkTexture> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
+        let NuccChunkConverter {
+            nucc_struct,
+            struct_info_map: _,
+            struct_reference_map: _,
+        } = converter;
+
+        let texture = nucc_struct
+            .downcast::<NuccTexture>()
+            .map(|c| *c)
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccTexture>(c.chunk_type()))?;
+
+        Ok(Box::new(NuccChunkTexture {
+            version: texture.version,
+            field00: texture.field00,
+            width: texture.width,
+            height: texture.height,
+            field06: texture.field06,
+            nut: texture.nut,
+        }))
+    }
+}
+
+impl NuccStruct for NuccTexture {
+    fn chunk_type(&self) -> NuccChunkType {
+        NuccChunkType::NuccChunkTexture
+    }
+
+    fn version(&self) -> u16 {
+        self.version
+    }
+}
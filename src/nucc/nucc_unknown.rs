@@ -10,8 +10,10 @@ pub struct NuccUnknown {
 
 impl_nucc_info!(NuccUnknown, struct_info);
 
-impl From<NuccStructConverter> for NuccUnknown {
-    fn from(converter: NuccStructConverter) -> Self {
+impl TryFrom<NuccStructConverter<'_>> for NuccUnknown {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccStructConverter<'_>) -> Result<Self> {
         let NuccStructConverter {
             nucc_chunk,
             struct_infos: _,
@@ -21,20 +23,21 @@ impl From<NuccStructConverter> for NuccUnknown {
         let chunk = nucc_chunk
             .downcast::<NuccChunkUnknown>()
             .map(|c| *c)
-            .ok()
-            .unwrap();
+            .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkUnknown>(c.chunk_type()))?;
 
-        Self {
+        Ok(Self {
             struct_info: Default::default(),
             data: chunk.data,
             chunk_type: chunk.chunk_type,
             version: chunk.version,
-        }
+        })
     }
 }
 
-impl From<NuccChunkConverter> for Box<NuccChunkUnknown> {
-    fn from(converter: NuccChunkConverter) -> Self {
+impl TryFrom<NuccChunkConverter> for Box<NuccChunkUnknown> {
+    type Error = XfbinError;
+
+    fn try_from(converter: NuccChunkConverter) -> Result<Self> {
         let NuccChunkConverter {
             nucc_struct,
             struct_info_map: _,
@@ -44,14 +47,13 @@ impl From<NuccChunkConverter> for Box<NuccChunkUnknown> {
         let unknown = nucc_struct
             .downcast::<NuccUnknown>()
             .map(|s| *s)
-            .ok()
-            .unwrap();
+            .map_err(|s| XfbinError::downcast_mismatch::<NuccUnknown>(s.chunk_type()))?;
 
-        Box::new(NuccChunkUnknown {
+        Ok(Box::new(NuccChunkUnknown {
             version: unknown.version,
             data: unknown.data,
             chunk_type: unknown.chunk_type,
-        })
+        }))
     }
 }
 
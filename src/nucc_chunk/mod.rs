@@ -11,6 +11,7 @@ pub mod nucc_chunk_lightpoint;
 pub mod nucc_chunk_layerset;
 pub mod nucc_chunk_ambient;
 pub mod nucc_chunk_morphmodel;
+pub mod nucc_chunk_texture;
 mod nucc_chunk_unknown;
 pub mod nucc_helper;
 
@@ -18,9 +19,10 @@ use binrw::{io::Cursor, BinRead, BinWrite};
 use std::{fmt, str::FromStr};
 
 use downcast_rs::{impl_downcast, Downcast};
-use std::error::Error;
 use strum_macros::{Display, EnumString};
 
+use super::error::{Result, XfbinError};
+
 pub use nucc_chunk_null::NuccChunkNull;
 pub use nucc_chunk_page::NuccChunkPage;
 pub use nucc_chunk_index::NuccChunkIndex;
@@ -34,6 +36,7 @@ pub use nucc_chunk_lightpoint::NuccChunkLightPoint;
 pub use nucc_chunk_layerset::NuccChunkLayerSet;
 pub use nucc_chunk_ambient::NuccChunkAmbient;
 pub use nucc_chunk_morphmodel::NuccChunkMorphModel;
+pub use nucc_chunk_texture::NuccChunkTexture;
 pub use nucc_chunk_unknown::NuccChunkUnknown;
 
 pub trait NuccChunk: Downcast + fmt::Debug {
@@ -46,7 +49,7 @@ pub trait NuccChunk: Downcast + fmt::Debug {
     fn read_boxed(
         input: &[u8],
         version: u16,
-    ) -> Result<(Vec<u8>, Box<dyn NuccChunk>), Box<dyn Error>>
+    ) -> Result<Box<dyn NuccChunk>>
     where
         Self: Sized + BinRead<Args = u16>,
     {
@@ -54,29 +57,31 @@ pub trait NuccChunk: Downcast + fmt::Debug {
         let mut cursor = Cursor::new(input);
         let result = Self::read_le_args(&mut cursor, version)?;
 
-        // Return the remaining data and the boxed value
-        Ok((input.into(), Box::new(result) as Box<dyn NuccChunk>))
+        // Return the boxed value, the input is only borrowed while it's parsed
+        Ok(Box::new(result))
     }
 
     fn write_boxed(
         boxed: Box<dyn NuccChunk>,
         output: &mut Cursor<Vec<u8>>,
-        version: u16,
-    ) -> Result<(), Box<dyn Error>>
+        _version: u16,
+    ) -> Result<()>
     where
         Self: Sized + BinWrite<Args = ()>,
     {
+        let chunk = boxed
+            .downcast::<Self>()
+            .map(|c| *c)
+            .map_err(|c| XfbinError::downcast_mismatch::<Self>(c.chunk_type()))?;
+
         // Serialize the data using binrw
-        Ok(Self::write_le(
-            &boxed.downcast::<Self>().map(|c| *c).ok().unwrap(),
-            output,
-        )?)
+        Ok(Self::write_le(&chunk, output)?)
     }
 }
 
 impl_downcast!(NuccChunk);
 
-#[derive(Debug, Clone, Display, EnumString, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Display, EnumString, PartialEq, Eq)]
 #[strum(serialize_all = "camelCase")]
 pub enum NuccChunkType {
     NuccChunkNull,
@@ -92,53 +97,49 @@ pub enum NuccChunkType {
     NuccChunkLayerSet,
     NuccChunkAmbient,
     NuccChunkMorphModel,
+    NuccChunkTexture,
+
+    #[default]
     NuccChunkUnknown,
    
     
 
 }
 
-impl Default for NuccChunkType {
-    fn default() -> Self {
-        NuccChunkType::NuccChunkUnknown
-    }
-}
-
 impl NuccChunkType {
     pub fn read_data(
-        data: Vec<u8>,
+        data: &[u8],
         chunk_type: &str,
         version: u16,
-    ) -> Result<(Vec<u8>, Box<dyn NuccChunk>), Box<dyn Error>> {
+    ) -> Result<Box<dyn NuccChunk>> {
         match NuccChunkType::from_str(chunk_type).unwrap_or_default() {
-            NuccChunkType::NuccChunkNull => Ok((data, Box::new(NuccChunkNull(version)))),
-            NuccChunkType::NuccChunkPage => NuccChunkPage::read_boxed(&data, version),
-            NuccChunkType::NuccChunkIndex => Ok((data, Box::new(NuccChunkIndex))),
-            NuccChunkType::NuccChunkBinary => NuccChunkBinary::read_boxed(&data, version),
+            NuccChunkType::NuccChunkNull => Ok(Box::new(NuccChunkNull(version))),
+            NuccChunkType::NuccChunkPage => NuccChunkPage::read_boxed(data, version),
+            NuccChunkType::NuccChunkIndex => Ok(Box::new(NuccChunkIndex)),
+            NuccChunkType::NuccChunkBinary => NuccChunkBinary::read_boxed(data, version),
             
-            NuccChunkType::NuccChunkAnm => NuccChunkAnm::read_boxed(&data, version), // Fix: Change `u16` to `()`
-            NuccChunkType::NuccChunkAnmStrm => NuccChunkAnmStrm::read_boxed(&data, version),
-            NuccChunkType::NuccChunkAnmStrmFrame => { NuccChunkAnmStrmFrame::read_boxed(&data, version)}
-            NuccChunkType::NuccChunkCamera => NuccChunkCamera::read_boxed(&data, version),
-            NuccChunkType::NuccChunkLightDirc => NuccChunkLightDirc::read_boxed(&data, version),
-            NuccChunkType::NuccChunkLightPoint => NuccChunkLightPoint::read_boxed(&data, version),
-            NuccChunkType::NuccChunkLayerSet => NuccChunkLayerSet::read_boxed(&data, version),
-            NuccChunkType::NuccChunkAmbient => NuccChunkAmbient::read_boxed(&data, version),
-            NuccChunkType::NuccChunkMorphModel => NuccChunkMorphModel::read_boxed(&data, version),
-
-
-            NuccChunkType::NuccChunkUnknown => Ok((
-                data.clone(),
-                Box::new(NuccChunkUnknown {
-                    version,
-                    chunk_type: chunk_type.to_string(),
-                    data: data.into(),
-                }),
-            )),
+            NuccChunkType::NuccChunkAnm => NuccChunkAnm::read_boxed(data, version), // Fix: Change `u16` to `()`
+            NuccChunkType::NuccChunkAnmStrm => NuccChunkAnmStrm::read_boxed(data, version),
+            NuccChunkType::NuccChunkAnmStrmFrame => { NuccChunkAnmStrmFrame::read_boxed(data, version)}
+            NuccChunkType::NuccChunkCamera => NuccChunkCamera::read_boxed(data, version),
+            NuccChunkType::NuccChunkLightDirc => NuccChunkLightDirc::read_boxed(data, version),
+            NuccChunkType::NuccChunkLightPoint => NuccChunkLightPoint::read_boxed(data, version),
+            NuccChunkType::NuccChunkLayerSet => NuccChunkLayerSet::read_boxed(data, version),
+            NuccChunkType::NuccChunkAmbient => NuccChunkAmbient::read_boxed(data, version),
+            NuccChunkType::NuccChunkMorphModel => NuccChunkMorphModel::read_boxed(data, version),
+            NuccChunkType::NuccChunkTexture => NuccChunkTexture::read_boxed(data, version),
+
+
+            // Unknown chunks are the only ones that keep a copy of their data
+            NuccChunkType::NuccChunkUnknown => Ok(Box::new(NuccChunkUnknown {
+                version,
+                chunk_type: chunk_type.to_string(),
+                data: data.to_vec(),
+            })),
         }
     }
 
-    pub fn write_data(boxed: Box<dyn NuccChunk>, version: u16) -> Result<Vec<u8>, Box<dyn Error>> {
+    pub fn write_data(boxed: Box<dyn NuccChunk>, version: u16) -> Result<Vec<u8>> {
         // Create a new cursor for writing
         let mut output = Cursor::new(Vec::new());
 
@@ -156,12 +157,13 @@ impl NuccChunkType {
             NuccChunkType::NuccChunkLayerSet => { NuccChunkLayerSet::write_boxed(boxed, &mut output, version)?; }
             NuccChunkType::NuccChunkAmbient => { NuccChunkAmbient::write_boxed(boxed, &mut output, version)?; }
             NuccChunkType::NuccChunkMorphModel => { NuccChunkMorphModel::write_boxed(boxed, &mut output, version)?; }
+            NuccChunkType::NuccChunkTexture => { NuccChunkTexture::write_boxed(boxed, &mut output, version)?; }
 
             NuccChunkType::NuccChunkUnknown => {
                 let unknown = boxed
                     .downcast::<NuccChunkUnknown>()
                     .map(|x| x.data)
-                    .unwrap();
+                    .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkUnknown>(c.chunk_type()))?;
                 unknown.write(&mut output)?;
             }
         }
@@ -1,16 +1,23 @@
 use binrw::binrw;
 
 use super::{NuccChunk, NuccChunkType};
+use super::nucc_helper::Vector3;
+
+/// Ambient light, with the same values as `AnmEntryAmbient` of animated lights
 
 #[binrw]
+#[brw(big)]
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct NuccChunkAmbient {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
-    #[br(count = 16)]
-    pub data: Vec<u8>,
+    pub color: Vector3,
+
+    /// Stored in place of the alpha of an RGBA color
+    pub intensity: f32,
 }
 
 impl NuccChunk for NuccChunkAmbient {
@@ -22,3 +29,24 @@ impl NuccChunk for NuccChunkAmbient {
         self.version
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::reread_chunk;
+    use crate::Result;
+
+    #[test]
+    fn ambient_chunk_test() -> Result<()> {
+        let mut data = Vec::new();
+        [0.25f32, 0.5, 0.75, 1.5].iter().for_each(|f| data.extend(f.to_be_bytes()));
+
+        let chunk = reread_chunk(NuccChunkType::NuccChunkAmbient, data)?;
+        let ambient = chunk.downcast_ref::<NuccChunkAmbient>().unwrap();
+
+        assert_eq!(ambient.color, Vector3 { x: 0.25, y: 0.5, z: 0.75 });
+        assert_eq!(ambient.intensity, 1.5);
+
+        Ok(())
+    }
+}
@@ -2,22 +2,26 @@
 //! nuccAnm is a chunk that contains animation data.
 //! The extension ".anm" stands for "Animation".
 //!
-use std::{default, io::{Read, Seek, Write}};
-use binrw::{binrw, BinRead, BinResult, BinWrite, ReadOptions, WriteOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use binrw::{binrw, BinRead, BinResult, BinWrite, ReadOptions, VecArgs, WriteOptions};
 
+use super::nucc_helper::{QuaternionShort, Vector3, Vector3Short, Vector4, VectorShort};
 use super::{NuccChunk, NuccChunkType};
+use crate::error::{Result, XfbinError};
+
+/// Frame size of anms that weren't read from a file
+pub const DEFAULT_FRAME_SIZE: u32 = 100;
 
 #[binrw]
 #[brw(big)]
 #[br(import_raw(version: u16))]
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct NuccChunkAnm {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
     pub frame_count: u32,
-
-    #[bw(calc = 100)]
     pub frame_size: u32,
 
     #[bw(calc = entries.len() as u16)]
@@ -53,6 +57,22 @@ pub struct NuccChunkAnm {
     pub entries: Vec<AnmEntry>,
 }
 
+impl Default for NuccChunkAnm {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            frame_count: 0,
+            frame_size: DEFAULT_FRAME_SIZE,
+            is_looped: 0,
+            clumps: Vec::new(),
+            other_entries_indices: Vec::new(),
+            unk_entry_indices: Vec::new(),
+            coord_parents: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
 #[binrw]
 #[derive(Debug, Clone, PartialEq)]
 pub struct AnmClump {
@@ -71,7 +91,6 @@ pub struct AnmClump {
     pub model_indices: Vec<u32>,
 }
 
-
 #[binrw]
 #[derive(Debug, Clone, PartialEq)]
 pub struct CoordParent {
@@ -79,7 +98,6 @@ pub struct CoordParent {
     pub child: AnmCoord,
 }
 
-
 #[binrw]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct AnmCoord {
@@ -87,9 +105,6 @@ pub struct AnmCoord {
     pub coord_index: u16,
 }
 
-
-
-
 #[binrw]
 #[brw(repr(u16))]
 #[derive(Debug, Clone, Default)]
@@ -106,69 +121,158 @@ pub enum EntryFormat {
     Unknown,
 }
 
-
 #[binrw]
 #[derive(Debug, Clone)]
 pub struct AnmEntry {
     pub coord: AnmCoord,
     pub entry_format: EntryFormat,
 
-    #[bw(calc = curve_headers.len() as u16)]
+    #[bw(calc = curves.len() as u16)]
     pub curve_count: u16,
 
+    /// Headers are written as they are while they still match their curve, otherwise only the
+    /// curve index is kept and the rest comes from the curve
     #[br(count = curve_count)]
+    #[bw(write_with = |curve_headers: &Vec<CurveHeader>, writer, wo, ()| write_curve_headers(curve_headers, curves, writer, wo, ()))]
     pub curve_headers: Vec<CurveHeader>,
 
     #[br(parse_with = read_curve(curve_headers.iter()))]
     #[br(align_after = 4)]
+    #[bw(write_with = |curves: &Vec<Curve>, writer, wo, ()| write_curves(curve_headers, curves, writer, wo, ()))]
     pub curves: Vec<Curve>,
 }
 
-
 #[binrw]
 #[derive(Debug, Clone, PartialEq)]
 pub struct CurveHeader {
     pub curve_index: u16,
+
+    #[br(map = |code: u16| CurveFormat::from(code))]
+    #[bw(map = u16::from)]
     pub curve_format: CurveFormat,
+
     pub frame_count: u16,
+
+    /// Size of the curve's keyframes in bytes, which can be more than the keyframes need
     pub curve_size: u16,
 }
 
+impl CurveHeader {
+    /// The header that `curve` is written with, which is this one if it still matches the curve
+    fn for_curve(&self, curve: &Curve) -> CurveHeader {
+        let header = curve.header(self.curve_index);
+
+        if self.curve_format == header.curve_format
+            && self.frame_count == header.frame_count
+            && self.curve_size >= header.curve_size
+        {
+            self.clone()
+        } else {
+            header
+        }
+    }
+}
 
-#[binrw]
-#[brw(repr(u16))]
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CurveFormat {
-    Vector3Fixed = 0x5,
-    Vector3Linear = 0x6,
-    Vector3Bezier = 0x7,
-    EulerXYZFixed  = 0x8,
-    EulerInterpolated = 0x9,
-    QuaternionLinear = 0xA,
-    FloatFixed = 0xB,
-    FloatLinear = 0xC,
-    Vector2Fixed = 0xD,
-    Vector2Linear = 0xE,
-    OpacityShortTable = 0xF,
-    ScaleShortTable = 0x10,
-    QuaternionShortTable = 0x11,
-    ColorRGBTable = 0x14,
-    Vector3Table = 0x15,
-    FloatTable = 0x16,
-    QuaternionTable = 0x17,
-    FloatTableNoInterp = 0x18,
-    Vector3ShortLinear = 0x19,
-    Vector3TableNoInterp = 0x1A,
-    QuaternionShortTableNoInterp = 0x1B,
-    OpacityShortTableNoInterp = 0x1D,
+    Vector3Fixed,
+    Vector3Linear,
+    Vector3Bezier,
+    EulerXYZFixed,
+    EulerInterpolated,
+    QuaternionLinear,
+    FloatFixed,
+    FloatLinear,
+    Vector2Fixed,
+    Vector2Linear,
+    OpacityShortTable,
+    ScaleShortTable,
+    QuaternionShortTable,
+    ColorRGBTable,
+    Vector3Table,
+    FloatTable,
+    QuaternionTable,
+    FloatTableNoInterp,
+
+    /// Linear keyframes with an i16 frame and a scale with the same fixed point as `ScaleShortTable`
+    Vector3ShortLinear,
+    Vector3TableNoInterp,
+    QuaternionShortTableNoInterp,
+    OpacityShortTableNoInterp,
+
+    /// A format that isn't supported, with its code in the file
+    Unknown(u16),
+}
 
-    #[default]
-    Unknown,
+impl Default for CurveFormat {
+    fn default() -> Self {
+        CurveFormat::Unknown(0)
+    }
+}
+
+impl From<u16> for CurveFormat {
+    fn from(code: u16) -> Self {
+        match code {
+            0x5 => CurveFormat::Vector3Fixed,
+            0x6 => CurveFormat::Vector3Linear,
+            0x7 => CurveFormat::Vector3Bezier,
+            0x8 => CurveFormat::EulerXYZFixed,
+            0x9 => CurveFormat::EulerInterpolated,
+            0xA => CurveFormat::QuaternionLinear,
+            0xB => CurveFormat::FloatFixed,
+            0xC => CurveFormat::FloatLinear,
+            0xD => CurveFormat::Vector2Fixed,
+            0xE => CurveFormat::Vector2Linear,
+            0xF => CurveFormat::OpacityShortTable,
+            0x10 => CurveFormat::ScaleShortTable,
+            0x11 => CurveFormat::QuaternionShortTable,
+            0x14 => CurveFormat::ColorRGBTable,
+            0x15 => CurveFormat::Vector3Table,
+            0x16 => CurveFormat::FloatTable,
+            0x17 => CurveFormat::QuaternionTable,
+            0x18 => CurveFormat::FloatTableNoInterp,
+            0x19 => CurveFormat::Vector3ShortLinear,
+            0x1A => CurveFormat::Vector3TableNoInterp,
+            0x1B => CurveFormat::QuaternionShortTableNoInterp,
+            0x1D => CurveFormat::OpacityShortTableNoInterp,
+            code => CurveFormat::Unknown(code),
+        }
+    }
+}
+
+impl From<&CurveFormat> for u16 {
+    fn from(curve_format: &CurveFormat) -> Self {
+        match curve_format {
+            CurveFormat::Vector3Fixed => 0x5,
+            CurveFormat::Vector3Linear => 0x6,
+            CurveFormat::Vector3Bezier => 0x7,
+            CurveFormat::EulerXYZFixed => 0x8,
+            CurveFormat::EulerInterpolated => 0x9,
+            CurveFormat::QuaternionLinear => 0xA,
+            CurveFormat::FloatFixed => 0xB,
+            CurveFormat::FloatLinear => 0xC,
+            CurveFormat::Vector2Fixed => 0xD,
+            CurveFormat::Vector2Linear => 0xE,
+            CurveFormat::OpacityShortTable => 0xF,
+            CurveFormat::ScaleShortTable => 0x10,
+            CurveFormat::QuaternionShortTable => 0x11,
+            CurveFormat::ColorRGBTable => 0x14,
+            CurveFormat::Vector3Table => 0x15,
+            CurveFormat::FloatTable => 0x16,
+            CurveFormat::QuaternionTable => 0x17,
+            CurveFormat::FloatTableNoInterp => 0x18,
+            CurveFormat::Vector3ShortLinear => 0x19,
+            CurveFormat::Vector3TableNoInterp => 0x1A,
+            CurveFormat::QuaternionShortTableNoInterp => 0x1B,
+            CurveFormat::OpacityShortTableNoInterp => 0x1D,
+            CurveFormat::Unknown(code) => *code,
+        }
+    }
 }
 
 impl CurveFormat {
-    pub fn size_per_frame(&self) -> usize {
-        match self {
+    pub fn size_per_frame(&self) -> Result<usize> {
+        Ok(match self {
             CurveFormat::OpacityShortTable | CurveFormat::OpacityShortTableNoInterp => 0x02,
             CurveFormat::ColorRGBTable => 0x03,
             CurveFormat::FloatFixed | CurveFormat::FloatTable | CurveFormat::FloatTableNoInterp => {
@@ -178,64 +282,133 @@ impl CurveFormat {
             CurveFormat::FloatLinear
             | CurveFormat::Vector2Fixed
             | CurveFormat::QuaternionShortTable
-            | CurveFormat::QuaternionShortTableNoInterp => 0x08,
+            | CurveFormat::QuaternionShortTableNoInterp
+            | CurveFormat::Vector3ShortLinear => 0x08,
             CurveFormat::Vector3Fixed
             | CurveFormat::EulerXYZFixed
             | CurveFormat::Vector2Linear
             | CurveFormat::Vector3Table
             | CurveFormat::Vector3TableNoInterp => 0x0C,
-            CurveFormat::Vector3Linear | CurveFormat::QuaternionTable => 0x10,
+            CurveFormat::Vector3Linear | CurveFormat::EulerInterpolated | CurveFormat::QuaternionTable => 0x10,
             CurveFormat::QuaternionLinear => 0x14,
-            CurveFormat::Vector3Bezier => todo!(),
-            CurveFormat::EulerInterpolated => todo!(),
-            CurveFormat::Vector3ShortLinear => todo!(),
-            CurveFormat::Unknown => todo!(),
-        }
+            CurveFormat::Vector3Bezier => 0x28,
+            CurveFormat::Unknown(_) => return Err(XfbinError::UnsupportedCurveFormat(self.clone())),
+        })
     }
 
 }
 
-#[binrw]
+/// A keyframe of a curve, the variant depends on the `CurveFormat`
+///
+/// Fixed-point formats are converted to and from floats, e.g. `QuaternionShortTable` keyframes are `Vec4`
+/// and `Vector3ShortLinear` keyframes are `Vec3Linear`
 #[derive(Debug, Clone, PartialEq)]
 pub enum Math {
+    Vec2 { channels: (f32, f32) },
+    Vec2Linear { frame: i32, channels: (f32, f32) },
     Vec3 { channels: (f32, f32, f32) },
     Vec3Linear { frame: i32, channels: (f32, f32, f32) },
+
+    /// Bezier keyframe with the tangent handles on each side of the value
+    Vec3Bezier {
+        frame: i32,
+        channels: (f32, f32, f32),
+        in_tangent: (f32, f32, f32),
+        out_tangent: (f32, f32, f32),
+    },
+
+    Vec4 { channels: (f32, f32, f32, f32) },
     Vec4Linear { frame: i32, channels: (f32, f32, f32, f32) },
     Float { channels: f32 },
     FloatLinear { frame: i32, channels: f32 },
-    I16Vec { channels: i16 },
-    I16Vec3 { channels: (i16, i16, i16) },
-    I16Vec4 { channels: (i16, i16, i16, i16) },
     Color { channels: (u8, u8, u8) },
 
-    // Add more variants as needed...
-    Unknown {}
-    
-}
-
-impl default::Default for Math {
-    fn default() -> Self {
-        Math::Unknown {}
-    }
+    /// The data of a curve with an unknown format, which is kept as is along with the frame count of its header
+    Raw { frame_count: u16, data: Vec<u8> },
 }
 
-
+/// The keyframes of a curve, one per frame of its `CurveHeader`
+///
+/// Linear formats store the frame of each keyframe, while fixed and table formats store one value per frame
 #[binrw]
 #[br(import_raw(header: CurveHeader))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Curve {
-    #[brw(ignore)]
-    pub curve_format: CurveFormat, // Used for writing the data
+    #[br(calc = header.curve_format.clone())]
+    #[bw(ignore)]
+    pub curve_format: CurveFormat,
 
-    #[brw(ignore)]
-    pub keyframe: Math,
+    #[br(parse_with = read_keyframes, args_raw = header)]
+    #[bw(write_with = |keyframes: &Vec<Math>, writer, wo, ()| write_keyframes(curve_format, keyframes, writer, wo, ()))]
+    pub keyframes: Vec<Math>,
+}
 
-    #[br(count = header.curve_format.size_per_frame() * header.frame_count as usize)]
-    #[bw(write_with = |_data, writer, wo, () | write_curve(curve_format.clone(), keyframe.clone(), writer, wo, ()))]
-    pub data: Vec<u8>,
+impl Curve {
+    /// The header of the curve in an entry, with the format, frame count and size of its keyframes
+    pub fn header(&self, curve_index: u16) -> CurveHeader {
+        let (frame_count, curve_size) = match self.keyframes.as_slice() {
+            [Math::Raw { frame_count, data }] => (*frame_count as usize, data.len()),
+            keyframes => (
+                keyframes.len(),
+                self.curve_format.size_per_frame().unwrap_or_default() * keyframes.len(),
+            ),
+        };
+
+        CurveHeader {
+            curve_index,
+            curve_format: self.curve_format.clone(),
+            frame_count: frame_count as u16,
+            curve_size: curve_size as u16,
+        }
+    }
 }
 
+fn write_curve_headers<W: Write + Seek>(
+    curve_headers: &[CurveHeader],
+    curves: &[Curve],
+    writer: &mut W,
+    wo: &WriteOptions,
+    _: ()
+) -> BinResult<()> {
+    if curve_headers.len() != curves.len() {
+        return Err(binrw::Error::Custom {
+            pos: writer.stream_position()?,
+            err: Box::new(XfbinError::CurveCountMismatch {
+                curves: curves.len(),
+                curve_headers: curve_headers.len(),
+            }),
+        });
+    }
 
+    for (header, curve) in curve_headers.iter().zip(curves) {
+        header.for_curve(curve).write_options(writer, wo, ())?;
+    }
+
+    Ok(())
+}
+
+fn write_curves<W: Write + Seek>(
+    curve_headers: &[CurveHeader],
+    curves: &[Curve],
+    writer: &mut W,
+    wo: &WriteOptions,
+    _: ()
+) -> BinResult<()> {
+    for (header, curve) in curve_headers.iter().zip(curves) {
+        let end = writer.stream_position()? + header.for_curve(curve).curve_size as u64;
+
+        curve.write_options(writer, wo, ())?;
+
+        // Curves that are larger than their keyframes are padded to their size
+        let pos = writer.stream_position()?;
+
+        if pos < end {
+            writer.write_all(&vec![0; (end - pos + curve_padding(end)) as usize])?;
+        }
+    }
+
+    Ok(())
+}
 
 fn read_curve<'it, R, T, Arg, Ret, It>(
     it: It,
@@ -253,42 +426,189 @@ where
     }
 }
 
-fn write_curve<R: Write + Seek>(
-    curve_format: CurveFormat,
-    values: Math,
-    writer: &mut R,
+/// Each curve is padded to 4 bytes
+fn curve_padding(pos: u64) -> u64 {
+    (4 - pos % 4) % 4
+}
+
+fn read_keyframes<R: Read + Seek>(
+    reader: &mut R,
+    options: &ReadOptions,
+    header: CurveHeader,
+) -> BinResult<Vec<Math>> {
+    let end = reader.stream_position()? + header.curve_size as u64;
+
+    let keyframes = match header.curve_format {
+        CurveFormat::Unknown(_) => vec![Math::Raw {
+            frame_count: header.frame_count,
+            data: BinRead::read_options(
+                reader,
+                options,
+                VecArgs::builder().count(header.curve_size as usize).finalize(),
+            )?,
+        }],
+        _ => (0..header.frame_count)
+            .map(|_| read_keyframe(reader, options, &header.curve_format))
+            .collect::<BinResult<Vec<_>>>()?,
+    };
+
+    // The curve size can be larger than the keyframes, and the padding to 4 bytes comes after it
+    let pos = reader.stream_position()?.max(end);
+    reader.seek(SeekFrom::Start(pos + curve_padding(pos)))?;
+
+    Ok(keyframes)
+}
+
+fn read_keyframe<R: Read + Seek>(reader: &mut R, options: &ReadOptions, curve_format: &CurveFormat) -> BinResult<Math> {
+    let pos = reader.stream_position()?;
+
+    Ok(match curve_format {
+        CurveFormat::Vector3Fixed
+        | CurveFormat::EulerXYZFixed
+        | CurveFormat::Vector3Table
+        | CurveFormat::Vector3TableNoInterp => Math::Vec3 { channels: BinRead::read_options(reader, options, ())? },
+
+        CurveFormat::Vector3Linear | CurveFormat::EulerInterpolated => Math::Vec3Linear {
+            frame: BinRead::read_options(reader, options, ())?,
+            channels: BinRead::read_options(reader, options, ())?,
+        },
+
+        CurveFormat::Vector3Bezier => Math::Vec3Bezier {
+            frame: BinRead::read_options(reader, options, ())?,
+            channels: BinRead::read_options(reader, options, ())?,
+            in_tangent: BinRead::read_options(reader, options, ())?,
+            out_tangent: BinRead::read_options(reader, options, ())?,
+        },
+
+        CurveFormat::Vector3ShortLinear => {
+            let frame = i16::read_options(reader, options, ())?;
+            let Vector3 { x, y, z } = Vector3Short::read_options(reader, options, ())?.to_scale();
+
+            Math::Vec3Linear {
+                frame: frame as i32,
+                channels: (x, y, z),
+            }
+        }
+
+        CurveFormat::QuaternionTable => Math::Vec4 { channels: BinRead::read_options(reader, options, ())? },
+
+        CurveFormat::QuaternionLinear => Math::Vec4Linear {
+            frame: BinRead::read_options(reader, options, ())?,
+            channels: BinRead::read_options(reader, options, ())?,
+        },
+
+        CurveFormat::FloatFixed | CurveFormat::FloatTable | CurveFormat::FloatTableNoInterp => {
+            Math::Float { channels: BinRead::read_options(reader, options, ())? }
+        }
+
+        CurveFormat::FloatLinear => Math::FloatLinear {
+            frame: BinRead::read_options(reader, options, ())?,
+            channels: BinRead::read_options(reader, options, ())?,
+        },
+
+        CurveFormat::Vector2Fixed => Math::Vec2 { channels: BinRead::read_options(reader, options, ())? },
+
+        CurveFormat::Vector2Linear => Math::Vec2Linear {
+            frame: BinRead::read_options(reader, options, ())?,
+            channels: BinRead::read_options(reader, options, ())?,
+        },
+
+        CurveFormat::OpacityShortTable | CurveFormat::OpacityShortTableNoInterp => Math::Float {
+            channels: VectorShort::read_options(reader, options, ())?.to_opacity(),
+        },
+
+        CurveFormat::ScaleShortTable => {
+            let Vector3 { x, y, z } = Vector3Short::read_options(reader, options, ())?.to_scale();
+            Math::Vec3 { channels: (x, y, z) }
+        }
+
+        CurveFormat::QuaternionShortTable | CurveFormat::QuaternionShortTableNoInterp => {
+            let Vector4 { x, y, z, w } = Vector4::from(&QuaternionShort::read_options(reader, options, ())?);
+            Math::Vec4 { channels: (x, y, z, w) }
+        }
+
+        CurveFormat::ColorRGBTable => Math::Color { channels: BinRead::read_options(reader, options, ())? },
+
+        curve_format => {
+            return Err(binrw::Error::Custom {
+                pos,
+                err: Box::new(XfbinError::UnsupportedCurveFormat(curve_format.clone())),
+            })
+        }
+    })
+}
+
+fn write_keyframes<W: Write + Seek>(
+    curve_format: &CurveFormat,
+    keyframes: &[Math],
+    writer: &mut W,
     wo: &WriteOptions,
-    
     _: ()
 ) -> BinResult<()> {
+    for keyframe in keyframes {
+        write_keyframe(curve_format, keyframe, writer, wo)?;
+    }
 
-    match (curve_format, values) {
+    let pos = writer.stream_position()?;
+    writer.write_all(&vec![0; curve_padding(pos) as usize])?;
+
+    Ok(())
+}
+
+fn write_keyframe<W: Write + Seek>(
+    curve_format: &CurveFormat,
+    keyframe: &Math,
+    writer: &mut W,
+    wo: &WriteOptions,
+) -> BinResult<()> {
+    match (curve_format, keyframe) {
         (CurveFormat::Vector3Fixed, Math::Vec3 { channels }) |
         (CurveFormat::EulerXYZFixed, Math::Vec3 { channels }) |
-        (CurveFormat::Vector3Table, Math::Vec3 { channels }) => {
-            channels.0.write_options(writer, wo, ())?;
-            channels.1.write_options(writer, wo, ())?;
-            channels.2.write_options(writer, wo, ())?;
+        (CurveFormat::Vector3Table, Math::Vec3 { channels }) |
+        (CurveFormat::Vector3TableNoInterp, Math::Vec3 { channels }) => {
+            channels.write_options(writer, wo, ())?;
+        }
+
+        (CurveFormat::Vector3Linear, Math::Vec3Linear { frame, channels }) |
+        (CurveFormat::EulerInterpolated, Math::Vec3Linear { frame, channels }) => {
+            frame.write_options(writer, wo, ())?;
+            channels.write_options(writer, wo, ())?;
         }
-    
-        (CurveFormat::Vector3Linear, Math::Vec3Linear { frame, channels }) => {
+
+        (CurveFormat::Vector3Bezier, Math::Vec3Bezier { frame, channels, in_tangent, out_tangent }) => {
             frame.write_options(writer, wo, ())?;
-            channels.0.write_options(writer, wo, ())?;
-            channels.1.write_options(writer, wo, ())?;
-            channels.2.write_options(writer, wo, ())?;
+            channels.write_options(writer, wo, ())?;
+            in_tangent.write_options(writer, wo, ())?;
+            out_tangent.write_options(writer, wo, ())?;
+        }
+
+        (CurveFormat::Vector3ShortLinear, Math::Vec3Linear { frame, channels: (x, y, z) }) => {
+            let frame = match i16::try_from(*frame) {
+                Ok(frame) => frame,
+                Err(_) => {
+                    return Err(binrw::Error::Custom {
+                        pos: writer.stream_position()?,
+                        err: Box::new(XfbinError::FrameOutOfRange(*frame)),
+                    })
+                }
+            };
+
+            frame.write_options(writer, wo, ())?;
+            Vector3Short::from_scale(&Vector3 { x: *x, y: *y, z: *z }).write_options(writer, wo, ())?;
+        }
+
+        (CurveFormat::QuaternionTable, Math::Vec4 { channels }) => {
+            channels.write_options(writer, wo, ())?;
         }
 
         (CurveFormat::QuaternionLinear, Math::Vec4Linear { frame, channels }) => {
             frame.write_options(writer, wo, ())?;
-            channels.0.write_options(writer, wo, ())?;
-            channels.1.write_options(writer, wo, ())?;
-            channels.2.write_options(writer, wo, ())?;
-            channels.3.write_options(writer, wo, ())?;
+            channels.write_options(writer, wo, ())?;
         }
 
         (CurveFormat::FloatFixed, Math::Float { channels }) |
-        (CurveFormat::FloatTable, Math::Float { channels}) |
-        (CurveFormat::FloatTableNoInterp, Math::Float { channels}) => {
+        (CurveFormat::FloatTable, Math::Float { channels }) |
+        (CurveFormat::FloatTableNoInterp, Math::Float { channels }) => {
             channels.write_options(writer, wo, ())?;
         }
 
@@ -297,41 +617,47 @@ fn write_curve<R: Write + Seek>(
             channels.write_options(writer, wo, ())?;
         }
 
-        (CurveFormat::OpacityShortTable, Math::I16Vec { channels }) |
-        (CurveFormat::OpacityShortTableNoInterp, Math::I16Vec { channels }) => {
+        (CurveFormat::Vector2Fixed, Math::Vec2 { channels }) => {
             channels.write_options(writer, wo, ())?;
         }
 
-        (CurveFormat::ScaleShortTable, Math::I16Vec3 { channels }) => {
-            channels.0.write_options(writer, wo, ())?;
-            channels.1.write_options(writer, wo, ())?;
-            channels.2.write_options(writer, wo, ())?;
+        (CurveFormat::Vector2Linear, Math::Vec2Linear { frame, channels }) => {
+            frame.write_options(writer, wo, ())?;
+            channels.write_options(writer, wo, ())?;
         }
 
-        (CurveFormat::QuaternionShortTable, Math::I16Vec4 { channels }) |
-        (CurveFormat::QuaternionShortTableNoInterp, Math::I16Vec4 { channels }) => {
-            channels.0.write_options(writer, wo, ())?;
-            channels.1.write_options(writer, wo, ())?;
-            channels.2.write_options(writer, wo, ())?;
-            channels.3.write_options(writer, wo, ())?;
+        (CurveFormat::OpacityShortTable, Math::Float { channels }) |
+        (CurveFormat::OpacityShortTableNoInterp, Math::Float { channels }) => {
+            VectorShort::from_opacity(*channels).write_options(writer, wo, ())?;
         }
 
-        (CurveFormat::ColorRGBTable, Math::Color { channels }) => {
-            channels.0.write_options(writer, wo, ())?;
-            channels.1.write_options(writer, wo, ())?;
-            channels.2.write_options(writer, wo, ())?;
+        (CurveFormat::ScaleShortTable, Math::Vec3 { channels: (x, y, z) }) => {
+            Vector3Short::from_scale(&Vector3 { x: *x, y: *y, z: *z }).write_options(writer, wo, ())?;
         }
-        // Handle other CurveFormat cases and Keyframe variants...
-        _ => todo!(),
-    }
-    
 
+        (CurveFormat::QuaternionShortTable, Math::Vec4 { channels: (x, y, z, w) }) |
+        (CurveFormat::QuaternionShortTableNoInterp, Math::Vec4 { channels: (x, y, z, w) }) => {
+            QuaternionShort::from(&Vector4 { x: *x, y: *y, z: *z, w: *w }).write_options(writer, wo, ())?;
+        }
 
-    Ok(())
-}
+        (CurveFormat::ColorRGBTable, Math::Color { channels }) => {
+            channels.write_options(writer, wo, ())?;
+        }
 
+        (CurveFormat::Unknown(_), Math::Raw { data, .. }) => {
+            writer.write_all(data)?;
+        }
 
+        (curve_format, _) => {
+            return Err(binrw::Error::Custom {
+                pos: writer.stream_position()?,
+                err: Box::new(XfbinError::UnsupportedCurveFormat(curve_format.clone())),
+            })
+        }
+    }
 
+    Ok(())
+}
 
 impl NuccChunk for NuccChunkAnm {
     fn chunk_type(&self) -> NuccChunkType {
@@ -346,3 +672,229 @@ impl NuccChunk for NuccChunkAnm {
         String::from(".anm")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc::NuccAnm;
+    use crate::test_util::*;
+    use crate::xfbin::Xfbin;
+    use crate::{read_xfbin_buf, write_xfbin_buf};
+
+    #[test]
+    fn anm_curves_test() -> Result<()> {
+        let buf = write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        })?;
+
+        let xfbin = read_xfbin_buf(buf.clone())?;
+        let anm = xfbin.get::<NuccAnm>("anm").unwrap();
+
+        assert_eq!(anm.entries[0].curves, fixture_curves());
+        assert_eq!(write_xfbin_buf(xfbin)?, buf);
+
+        // The opacity curve is padded to 4 bytes before the location curve
+        let anm = fixture_structs(121).remove(1).downcast::<NuccAnm>().unwrap();
+        let chunk = NuccChunkAnm {
+            version: 121,
+            frame_count: anm.frame_count,
+            entries: anm.entries,
+            ..Default::default()
+        };
+
+        let data = NuccChunkType::write_data(Box::new(chunk), 121)?;
+        let curves_start = data.len() - (8 + 32);
+
+        assert_eq!(data[curves_start..curves_start + 8], [0x3F, 0xFF, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(data[curves_start + 8..curves_start + 12], 0i32.to_be_bytes());
+
+        let chunk = reread_chunk(NuccChunkType::NuccChunkAnm, data)?;
+        let chunk = chunk.downcast_ref::<NuccChunkAnm>().unwrap();
+        assert_eq!(chunk.entries[0].curves, fixture_curves());
+
+        // Keyframes have to match the format of their curve
+        let mut curves = fixture_curves();
+        curves[0].curve_format = CurveFormat::Vector3Table;
+
+        let mut structs = fixture_structs(121);
+        structs[1].downcast_mut::<NuccAnm>().unwrap().entries[0].curves = curves;
+
+        assert!(write_xfbin_buf(Xfbin {
+            pages: vec![page(structs)],
+            ..Default::default()
+        })
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn anm_curve_headers_test() -> Result<()> {
+        let write_entry = |entry: AnmEntry| {
+            NuccChunkType::write_data(
+                Box::new(NuccChunkAnm {
+                    version: 121,
+                    entries: vec![entry],
+                    ..Default::default()
+                }),
+                121,
+            )
+        };
+
+        // Headers that don't match their curves are written from the curves, keeping the curve index
+        let entry = AnmEntry {
+            coord: coord(0, 0),
+            entry_format: EntryFormat::Coord,
+            curve_headers: vec![
+                curve_header(3, CurveFormat::FloatTable, 1, 4),
+                curve_header(0, CurveFormat::Vector3Fixed, 0, 0),
+            ],
+            curves: fixture_curves(),
+        };
+
+        let chunk = reread_chunk(NuccChunkType::NuccChunkAnm, write_entry(entry)?)?;
+        let chunk = chunk.downcast_ref::<NuccChunkAnm>().unwrap();
+
+        assert_eq!(
+            chunk.entries[0].curve_headers,
+            vec![
+                curve_header(3, CurveFormat::OpacityShortTable, 3, 6),
+                curve_header(0, CurveFormat::Vector3Linear, 2, 32),
+            ]
+        );
+        assert_eq!(chunk.entries[0].curves, fixture_curves());
+
+        // Every curve needs a header for its curve index
+        let entry = AnmEntry {
+            coord: coord(0, 0),
+            entry_format: EntryFormat::Coord,
+            curve_headers: vec![curve_header(0, CurveFormat::OpacityShortTable, 3, 6)],
+            curves: fixture_curves(),
+        };
+
+        assert!(write_entry(entry).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn anm_stored_sizes_test() -> Result<()> {
+        // A frame size other than 100, and an opacity curve whose size is padded past its keyframes
+        let curve_headers = vec![
+            curve_header(0, CurveFormat::OpacityShortTable, 3, 12),
+            curve_header(1, CurveFormat::Vector3Linear, 2, 32),
+        ];
+
+        let chunk = NuccChunkAnm {
+            version: 121,
+            frame_count: 60,
+            frame_size: 30,
+            entries: vec![AnmEntry {
+                coord: coord(0, 0),
+                entry_format: EntryFormat::Coord,
+                curve_headers: curve_headers.clone(),
+                curves: fixture_curves(),
+            }],
+            ..Default::default()
+        };
+
+        let data = NuccChunkType::write_data(Box::new(chunk), 121)?;
+        assert_eq!(data[4..8], 30u32.to_be_bytes());
+        assert_eq!(data.len(), 0x14 + 8 + 8 * 2 + 12 + 32);
+
+        let chunk = reread_chunk(NuccChunkType::NuccChunkAnm, data)?;
+        let chunk = chunk.downcast_ref::<NuccChunkAnm>().unwrap();
+
+        assert_eq!(chunk.frame_size, 30);
+        assert_eq!(chunk.entries[0].curve_headers, curve_headers);
+        assert_eq!(chunk.entries[0].curves, fixture_curves());
+
+        Ok(())
+    }
+
+    #[test]
+    fn anm_curve_formats_test() -> Result<()> {
+        let curves = vec![
+            (CurveFormat::Vector2Fixed, vec![Math::Vec2 { channels: (1.0, 2.0) }]),
+            (
+                CurveFormat::Vector2Linear,
+                vec![Math::Vec2Linear {
+                    frame: 10,
+                    channels: (1.0, 2.0),
+                }],
+            ),
+            (CurveFormat::Vector3TableNoInterp, vec![Math::Vec3 { channels: (1.0, 2.0, 3.0) }; 3]),
+            (CurveFormat::QuaternionTable, vec![Math::Vec4 { channels: (0.0, 0.0, 0.0, 1.0) }; 2]),
+            (
+                CurveFormat::Vector3ShortLinear,
+                vec![Math::Vec3Linear {
+                    frame: 5,
+                    channels: (-0.5, 0.0, 2.0),
+                }],
+            ),
+            (
+                CurveFormat::Vector3Bezier,
+                vec![Math::Vec3Bezier {
+                    frame: 0,
+                    channels: (1.0, 2.0, 3.0),
+                    in_tangent: (0.5, 1.5, 2.5),
+                    out_tangent: (1.5, 2.5, 3.5),
+                }],
+            ),
+            (
+                CurveFormat::EulerInterpolated,
+                vec![Math::Vec3Linear {
+                    frame: 20,
+                    channels: (0.0, 1.5, 3.0),
+                }],
+            ),
+            (
+                CurveFormat::Unknown(0x30),
+                vec![Math::Raw {
+                    frame_count: 2,
+                    data: bytes(5),
+                }],
+            ),
+        ];
+
+        let curve_headers = curves
+            .iter()
+            .enumerate()
+            .map(|(i, (curve_format, keyframes))| {
+                let (frame_count, curve_size) = match curve_format {
+                    CurveFormat::Unknown(_) => (2, 5),
+                    _ => (keyframes.len(), curve_format.size_per_frame().unwrap() * keyframes.len()),
+                };
+
+                curve_header(i as u16, curve_format.clone(), frame_count as u16, curve_size as u16)
+            })
+            .collect::<Vec<_>>();
+
+        let curves = curves
+            .into_iter()
+            .map(|(curve_format, keyframes)| Curve { curve_format, keyframes })
+            .collect::<Vec<_>>();
+
+        let chunk = NuccChunkAnm {
+            version: 121,
+            frame_count: 100,
+            entries: vec![AnmEntry {
+                coord: coord(0, 0),
+                entry_format: EntryFormat::Coord,
+                curve_headers: curve_headers.clone(),
+                curves: curves.clone(),
+            }],
+            ..Default::default()
+        };
+
+        let data = NuccChunkType::write_data(Box::new(chunk), 121)?;
+        let chunk = reread_chunk(NuccChunkType::NuccChunkAnm, data)?;
+        let chunk = chunk.downcast_ref::<NuccChunkAnm>().unwrap();
+
+        assert_eq!(chunk.entries[0].curve_headers, curve_headers);
+        assert_eq!(chunk.entries[0].curves, curves);
+
+        Ok(())
+    }
+}
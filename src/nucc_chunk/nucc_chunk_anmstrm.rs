@@ -13,12 +13,11 @@ use super::{NuccChunk, NuccChunkType};
 #[derive(Debug, Clone, PartialEq)]
 
 pub struct NuccChunkAnmStrm {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
     pub frame_count: u32,
-
-    #[bw(calc = 100)]
     pub frame_size: u32,
 
     #[bw(calc = entries.len() as u16)]
@@ -29,7 +28,7 @@ pub struct NuccChunkAnmStrm {
     #[bw(calc = clumps.len() as u16)]
     pub clump_count: u16,
 
-    #[bw(calc = other_entry_indices.len() as u16)]
+    #[bw(calc = (other_entry_indices.len() as u16).saturating_sub(*unk_entry_count))]
     pub other_entry_count: u16,
 
     pub unk_entry_count: u16,
@@ -67,6 +66,7 @@ pub struct AnmStrmClump {
 
     #[br(count = model_count)]
     #[br(pad_after = 4 * (model_count as usize))]
+    #[bw(pad_after = 4 * model_indices.len())]
     pub model_indices: Vec<u32>,
 }
 
@@ -13,7 +13,8 @@ use super::nucc_helper::*;
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct NuccChunkAnmStrmFrame {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
     pub frame_number: u32,
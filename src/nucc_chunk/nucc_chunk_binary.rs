@@ -7,7 +7,8 @@ use super::{NuccChunk, NuccChunkType};
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct NuccChunkBinary {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
     #[bw(calc = data.len() as u32)]
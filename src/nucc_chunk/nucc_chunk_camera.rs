@@ -1,4 +1,4 @@
-use binrw::binrw;
+use binrw::{binrw, until_eof};
 
 use super::{NuccChunk, NuccChunkType};
 
@@ -7,11 +7,16 @@ use super::{NuccChunk, NuccChunkType};
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct NuccChunkCamera {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
-    #[brw(pad_before = 4)]
+    pub field00: u32,
     pub fov: f32,
+
+    /// Data after the fov, which some versions have and is kept as is
+    #[br(parse_with = until_eof)]
+    pub extra: Vec<u8>,
 }
 
 impl NuccChunk for NuccChunkCamera {
@@ -22,4 +27,31 @@ impl NuccChunk for NuccChunkCamera {
     fn version(&self) -> u16 {
         self.version
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{bytes, reread_chunk};
+    use crate::Result;
+
+    #[test]
+    fn camera_chunk_test() -> Result<()> {
+        // The field before the fov isn't always 0, and some cameras have more data after it
+        for extra in [Vec::new(), bytes(12)] {
+            let mut data = Vec::new();
+            data.extend(0x12345678u32.to_be_bytes());
+            data.extend(60.0f32.to_be_bytes());
+            data.extend(&extra);
+
+            let chunk = reread_chunk(NuccChunkType::NuccChunkCamera, data)?;
+            let camera = chunk.downcast_ref::<NuccChunkCamera>().unwrap();
+
+            assert_eq!(camera.field00, 0x12345678);
+            assert_eq!(camera.fov, 60.0);
+            assert_eq!(camera.extra, extra);
+        }
+
+        Ok(())
+    }
+}
@@ -1,16 +1,50 @@
-use binrw::binrw;
+use binrw::{binrw, until_eof, BinRead, BinResult, ReadOptions};
+use std::io::{Read, Seek, SeekFrom};
 
 use super::{NuccChunk, NuccChunkType};
 
+/// Size of a single `LayerSetLayer`
+const LAYER_SIZE: u64 = 0x10;
+
 #[binrw]
+#[brw(big)]
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct NuccChunkLayerSet {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
-    #[br(count = 466)]
-    pub data: Vec<u8>,
+    pub field00: u16,
+
+    /// There's no count, so as many layers as fit in the chunk are read
+    #[br(parse_with = read_layers)]
+    pub layers: Vec<LayerSetLayer>,
+
+    /// Data after the last layer, which is kept as is
+    #[br(parse_with = until_eof)]
+    pub extra: Vec<u8>,
+}
+
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LayerSetLayer {
+    /// Index of the layer's chunk in the page's chunk maps
+    pub chunk_index: u32,
+    pub flags: u32,
+
+    pub field08: u32,
+    pub field0c: u32,
+}
+
+fn read_layers<R: Read + Seek>(reader: &mut R, options: &ReadOptions, _: ()) -> BinResult<Vec<LayerSetLayer>> {
+    let start = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    (0..(end - start) / LAYER_SIZE)
+        .map(|_| LayerSetLayer::read_options(reader, options, ()))
+        .collect()
 }
 
 impl NuccChunk for NuccChunkLayerSet {
@@ -22,3 +56,31 @@ impl NuccChunk for NuccChunkLayerSet {
         self.version
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{bytes, reread_chunk};
+    use crate::Result;
+
+    #[test]
+    fn layerset_chunk_test() -> Result<()> {
+        // Layer sets of any size are read, with the bytes that don't fit in a layer kept as they are
+        for (size, layer_count) in [(466, 29), (2 + 0x10 * 3 + 5, 3), (2, 0)] {
+            let chunk = reread_chunk(NuccChunkType::NuccChunkLayerSet, bytes(size))?;
+            let layerset = chunk.downcast_ref::<NuccChunkLayerSet>().unwrap();
+
+            assert_eq!(layerset.field00, 0x0001);
+            assert_eq!(layerset.layers.len(), layer_count);
+            assert_eq!(layerset.extra.len(), (size - 2) % 0x10);
+        }
+
+        let chunk = reread_chunk(NuccChunkType::NuccChunkLayerSet, bytes(466))?;
+        let layerset = chunk.downcast_ref::<NuccChunkLayerSet>().unwrap();
+
+        assert_eq!(layerset.layers[0].chunk_index, 0x02030405);
+        assert_eq!(layerset.layers[28].field0c, 0xCECFD0D1);
+
+        Ok(())
+    }
+}
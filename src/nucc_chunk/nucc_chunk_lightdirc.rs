@@ -1,16 +1,28 @@
 use binrw::binrw;
 
 use super::{NuccChunk, NuccChunkType};
+use super::nucc_helper::{Vector3, Vector4};
 
+/// A directional light, with the same values as `AnmEntryLightDirc` of animated lights
 #[binrw]
+#[brw(big)]
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct NuccChunkLightDirc {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
-    #[br(count = 64)]
-    pub data: Vec<u8>,
+    pub color: Vector3,
+    pub intensity: f32,
+
+    /// Rotation of the light as a quaternion
+    pub direction: Vector4,
+
+    pub flags: u32,
+
+    /// Remaining bytes, which are kept as is
+    pub unk: [u8; 28],
 }
 
 impl NuccChunk for NuccChunkLightDirc {
@@ -22,3 +34,31 @@ impl NuccChunk for NuccChunkLightDirc {
         self.version
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{bytes, reread_chunk};
+    use crate::Result;
+
+    #[test]
+    fn lightdirc_chunk_test() -> Result<()> {
+        let mut data = Vec::new();
+        [1.0f32, 0.5, 0.25, 2.0, 0.0, 0.0, 0.0, 1.0]
+            .iter()
+            .for_each(|f| data.extend(f.to_be_bytes()));
+        data.extend(3u32.to_be_bytes());
+        data.extend(bytes(28));
+
+        let chunk = reread_chunk(NuccChunkType::NuccChunkLightDirc, data)?;
+        let lightdirc = chunk.downcast_ref::<NuccChunkLightDirc>().unwrap();
+
+        assert_eq!(lightdirc.color, Vector3 { x: 1.0, y: 0.5, z: 0.25 });
+        assert_eq!(lightdirc.intensity, 2.0);
+        assert_eq!(lightdirc.direction, Vector4 { x: 0.0, y: 0.0, z: 0.0, w: 1.0 });
+        assert_eq!(lightdirc.flags, 3);
+        assert_eq!(lightdirc.unk[..], bytes(28));
+
+        Ok(())
+    }
+}
@@ -1,16 +1,28 @@
 use binrw::binrw;
 
 use super::{NuccChunk, NuccChunkType};
+use super::nucc_helper::Vector3;
 
+/// A point light, with the same values as `AnmEntryLightPoint` of animated lights
 #[binrw]
+#[brw(big)]
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct NuccChunkLightPoint {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
-    #[br(count = 64)]
-    pub data: Vec<u8>,
+    pub color: Vector3,
+    pub position: Vector3,
+    pub intensity: f32,
+    pub radius: f32,
+    pub falloff: f32,
+
+    pub flags: u32,
+
+    /// Remaining bytes, which are kept as is
+    pub unk: [u8; 24],
 }
 
 impl NuccChunk for NuccChunkLightPoint {
@@ -22,3 +34,33 @@ impl NuccChunk for NuccChunkLightPoint {
         self.version
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{bytes, reread_chunk};
+    use crate::Result;
+
+    #[test]
+    fn lightpoint_chunk_test() -> Result<()> {
+        let mut data = Vec::new();
+        [0.0f32, 1.0, 0.5, 1.0, 2.0, 3.0, 4.0, 100.0, 0.75]
+            .iter()
+            .for_each(|f| data.extend(f.to_be_bytes()));
+        data.extend(1u32.to_be_bytes());
+        data.extend(bytes(24));
+
+        let chunk = reread_chunk(NuccChunkType::NuccChunkLightPoint, data)?;
+        let lightpoint = chunk.downcast_ref::<NuccChunkLightPoint>().unwrap();
+
+        assert_eq!(lightpoint.color, Vector3 { x: 0.0, y: 1.0, z: 0.5 });
+        assert_eq!(lightpoint.position, Vector3 { x: 1.0, y: 2.0, z: 3.0 });
+        assert_eq!(lightpoint.intensity, 4.0);
+        assert_eq!(lightpoint.radius, 100.0);
+        assert_eq!(lightpoint.falloff, 0.75);
+        assert_eq!(lightpoint.flags, 1);
+        assert_eq!(lightpoint.unk[..], bytes(24));
+
+        Ok(())
+    }
+}
@@ -3,14 +3,27 @@ use binrw::binrw;
 use super::{NuccChunk, NuccChunkType};
 
 #[binrw]
+#[brw(big)]
 #[br(import_raw(version: u16))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct NuccChunkMorphModel {
-    #[brw(ignore)]
+    #[br(calc = version)]
+    #[bw(ignore)]
     pub version: u16,
 
-    #[br(count = 28)]
-    pub data: Vec<u8>,
+    pub flags: u32,
+
+    /// Page-local chunk map indices of the clump and the morphed model
+    pub clump_index: u32,
+    pub model_index: u32,
+
+    /// The morph targets are consecutive references of the page, in the same order as the
+    /// weights of `AnmEntryMorphModel`
+    pub target_count: u32,
+    pub target_reference_index: u32,
+
+    pub field14: u32,
+    pub field18: u32,
 }
 
 impl NuccChunk for NuccChunkMorphModel {
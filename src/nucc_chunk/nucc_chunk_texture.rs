@@ -1,31 +1,62 @@
 //! # nuccTexture
 //! nuccTexture is a container for NUT image textures  used in some CyberConnect2 games (e.g. Naruto Shippuden: Ultimate Ninja Storm 4).
 //! The extension ".nut" may stand for "NU Texture".
-//! 
+//!
 use binrw::binrw;
 
 use super::{NuccChunk, NuccChunkType};
+use crate::error::{Result, XfbinError};
+
+/// Size of a `NutTexture` header without its mipmap size table
+const NUT_TEXTURE_HEADER_SIZE: usize = 0x50;
+
+/// Size of the cubemap face sizes that cubemap headers have before the mipmap size table
+const NUT_CUBEMAP_HEADER_SIZE: usize = 0x10;
+
+/// `caps2` flag of cubemaps, the same as the DDS one
+pub const NUT_CAPS2_CUBEMAP: u32 = 0x200;
 
 #[binrw]
-#[br(big)]
-#[derive(Debug, Clone, PartialEq)]
-pub struct NuccTexture {
+#[brw(big)]
+#[br(import_raw(version: u16))]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NuccChunkTexture {
+    #[br(calc = version)]
+    #[bw(ignore)]
+    pub version: u16,
+
     pub field00: u16,
     pub width: u16,
     pub height: u16,
     pub field06: u16,
 
+    #[bw(calc = nut.size() as u32)]
     pub size: u32,
 
     pub nut: Nut
 }
 
+impl NuccChunk for NuccChunkTexture {
+    fn chunk_type(&self) -> NuccChunkType {
+        NuccChunkType::NuccChunkTexture
+    }
+
+    fn version(&self) -> u16 {
+        self.version
+    }
+
+    fn extension(&self) -> String {
+        String::from(".nut")
+    }
+}
+
 #[binrw]
 #[brw(magic = b"NTP3")]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Nut {
     pub version: u16,
 
+    #[bw(calc = textures.len() as u16)]
     #[brw(pad_after = 8)]
     pub count: u16,
 
@@ -33,13 +64,24 @@ pub struct Nut {
     pub textures: Vec<NutTexture>
 }
 
+impl Nut {
+    /// Size of the NTP3 container, including the magic
+    pub fn size(&self) -> usize {
+        0x10 + self.textures.iter().map(|t| t.total_size()).sum::<usize>()
+    }
+}
+
 #[binrw]
 #[derive(Debug, Clone, PartialEq)]
 pub struct NutTexture {
+    #[bw(calc = self.total_size() as u32)]
     #[brw(pad_after = 4)]
     pub total_size: u32,
+
+    #[bw(calc = texture_data.len() as u32)]
     pub data_size: u32,
 
+    #[bw(calc = self.header_size() as u16)]
     #[brw(pad_after = 2)]
     pub header_size: u16,
 
@@ -50,23 +92,241 @@ pub struct NutTexture {
     #[brw(pad_after = 4)]
     pub height: u16,
 
+    /// Cubemap flags in the same form as DDS caps2, with `NUT_CAPS2_CUBEMAP` and the faces
     pub caps2: u32,
 
+    /// Kept as read, it's set to the cubemap flags of `caps2` for cubemaps imported from DDS
     #[brw(pad_after = 12)]
     pub cubemap_format: u32,
 
-    #[br(count = mipmap_count)]
+    /// Size of each face of a cubemap followed by the rest of its 0x10 bytes, only present for cubemaps
+    #[br(if(caps2 & NUT_CAPS2_CUBEMAP != 0))]
+    pub cubemap_sizes: Option<[u32; 4]>,
+
+    /// Size of each mipmap, only present when there's more than one mipmap
+    #[br(count = mipmap_table_len(mipmap_header_size(header_size, &cubemap_sizes), mipmap_count))]
     pub mipmaps: Vec<u32>,
 
-    #[brw(pad_after = 12)]
+    /// Header bytes between the mipmap size table, which is padded to 0x10 bytes, and the eXt block
+    #[br(pad_before = mipmap_table_alignment(mipmaps.len()).min(mipmap_table_padding(mipmap_header_size(header_size, &cubemap_sizes), mipmaps.len())))]
+    #[bw(pad_before = mipmap_table_alignment(mipmaps.len()))]
+    #[br(count = mipmap_table_padding(mipmap_header_size(header_size, &cubemap_sizes), mipmaps.len()).saturating_sub(mipmap_table_alignment(mipmaps.len())))]
+    pub extra_header: Vec<u8>,
+
     pub ext: [u8; 4],
 
+    /// Size of the eXt block, including the GIDX block
+    pub ext_size: u32,
+
     #[brw(pad_after = 4)]
+    pub ext_header_size: u32,
+
     pub gidx: [u8; 4],
+    pub gidx_size: u32,
 
     #[brw(pad_after = 4)]
     pub hash: u32,
 
     #[br(count = data_size)]
     pub texture_data: Vec<u8>
-}
\ No newline at end of file
+}
+
+impl Default for NutTexture {
+    fn default() -> Self {
+        Self {
+            mipmap_count: 1,
+            pixel_format: 0,
+            width: 0,
+            height: 0,
+            caps2: 0,
+            cubemap_format: 0,
+            cubemap_sizes: None,
+            mipmaps: Vec::new(),
+            extra_header: Vec::new(),
+            ext: *b"eXt\0",
+            ext_size: 0x20,
+            ext_header_size: 0x10,
+            gidx: *b"GIDX",
+            gidx_size: 0x10,
+            hash: 0,
+            texture_data: Vec::new(),
+        }
+    }
+}
+
+impl NutTexture {
+    pub fn format(&self) -> Result<NutPixelFormat> {
+        NutPixelFormat::try_from(self.pixel_format)
+    }
+
+    /// Whether the texture has all six faces of a cubemap, using the DDS cubemap flag
+    pub fn is_cubemap(&self) -> bool {
+        self.caps2 & NUT_CAPS2_CUBEMAP != 0
+    }
+
+    pub fn header_size(&self) -> usize {
+        NUT_TEXTURE_HEADER_SIZE
+            + self.cubemap_sizes.map_or(0, |_| NUT_CUBEMAP_HEADER_SIZE)
+            + self.mipmaps.len() * 4
+            + mipmap_table_alignment(self.mipmaps.len())
+            + self.extra_header.len()
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.header_size() + self.texture_data.len()
+    }
+}
+
+/// Size of the header without the cubemap face sizes, if it has them
+fn mipmap_header_size(header_size: u16, cubemap_sizes: &Option<[u32; 4]>) -> u16 {
+    header_size.saturating_sub(cubemap_sizes.map_or(0, |_| NUT_CUBEMAP_HEADER_SIZE as u16))
+}
+
+/// Number of mipmap sizes that fit in the header, which has no table for a single mipmap in some files
+fn mipmap_table_len(header_size: u16, mipmap_count: u16) -> usize {
+    let table_size = (header_size as usize).saturating_sub(NUT_TEXTURE_HEADER_SIZE);
+
+    (mipmap_count as usize).min(table_size / 4)
+}
+
+/// Padding that aligns the mipmap size table to 0x10 bytes
+fn mipmap_table_alignment(mipmap_table_len: usize) -> usize {
+    (0x10 - (mipmap_table_len * 4) % 0x10) % 0x10
+}
+
+/// Number of header bytes between the mipmap size table and the eXt block
+fn mipmap_table_padding(header_size: u16, mipmap_table_len: usize) -> usize {
+    (header_size as usize)
+        .saturating_sub(NUT_TEXTURE_HEADER_SIZE)
+        .saturating_sub(mipmap_table_len * 4)
+}
+
+/// Pixel formats of `NutTexture::pixel_format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NutPixelFormat {
+    Dxt1 = 0,
+    Dxt3 = 1,
+    Dxt5 = 2,
+    B5G5R5A1 = 6,
+    B4G4R4A4 = 7,
+    B5G6R5 = 8,
+    R8G8B8A8 = 14,
+    B8G8R8A8 = 17,
+    Bc4 = 21,
+    Bc5 = 22,
+}
+
+impl TryFrom<u16> for NutPixelFormat {
+    type Error = XfbinError;
+
+    fn try_from(pixel_format: u16) -> Result<Self> {
+        Ok(match pixel_format {
+            0 => Self::Dxt1,
+            1 => Self::Dxt3,
+            2 => Self::Dxt5,
+            6 => Self::B5G5R5A1,
+            7 => Self::B4G4R4A4,
+            8 => Self::B5G6R5,
+            14 => Self::R8G8B8A8,
+            17 => Self::B8G8R8A8,
+            21 => Self::Bc4,
+            22 => Self::Bc5,
+            any => return Err(XfbinError::UnsupportedPixelFormat(any)),
+        })
+    }
+}
+
+impl NutPixelFormat {
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, Self::Dxt1 | Self::Dxt3 | Self::Dxt5 | Self::Bc4 | Self::Bc5)
+    }
+
+    /// Bytes per 4x4 block for compressed formats, or per pixel otherwise
+    pub fn block_size(&self) -> usize {
+        match self {
+            Self::Dxt1 | Self::Bc4 => 8,
+            Self::Dxt3 | Self::Dxt5 | Self::Bc5 => 16,
+            Self::B5G5R5A1 | Self::B4G4R4A4 | Self::B5G6R5 => 2,
+            Self::R8G8B8A8 | Self::B8G8R8A8 => 4,
+        }
+    }
+
+    /// Size of a single mipmap level of the given dimensions
+    pub fn surface_size(&self, width: usize, height: usize) -> usize {
+        if self.is_compressed() {
+            width.div_ceil(4).max(1) * height.div_ceil(4).max(1) * self.block_size()
+        } else {
+            width.max(1) * height.max(1) * self.block_size()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::bytes;
+    use binrw::{io::Cursor, BinReaderExt, BinWrite};
+
+    #[test]
+    fn nut_texture_header_test() -> Result<()> {
+        // A single mipmap texture without a mipmap size table, as some files have
+        let mut data = vec![0, 0, 0, 0x54, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0x50, 0, 0];
+        data.extend([0, 1, 0, 14, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([0; 16]);
+        data.extend(b"eXt\0");
+        data.extend([0, 0, 0, 0x20, 0, 0, 0, 0x10, 0, 0, 0, 0]);
+        data.extend(b"GIDX");
+        data.extend([0, 0, 0, 0x10, 0, 0, 0x12, 0x34, 0, 0, 0, 0]);
+        data.extend([1, 2, 3, 4]);
+
+        let texture = Cursor::new(&data).read_be::<NutTexture>()?;
+
+        assert!(texture.mipmaps.is_empty());
+        assert_eq!(texture.hash, 0x1234);
+        assert_eq!(texture.texture_data, vec![1, 2, 3, 4]);
+        assert_eq!(texture.header_size(), 0x50);
+
+        let mut cursor = Cursor::new(Vec::new());
+        texture.write_be(&mut cursor)?;
+        assert_eq!(cursor.into_inner(), data);
+
+        // The mipmap size table is padded to 0x10 bytes
+        let texture = NutTexture {
+            mipmap_count: 5,
+            mipmaps: vec![1; 5],
+            ..texture
+        };
+        assert_eq!(texture.header_size(), 0x70);
+
+        let mut cursor = Cursor::new(Vec::new());
+        texture.write_be(&mut cursor)?;
+        let buf = cursor.into_inner();
+
+        assert_eq!(buf.len(), texture.total_size());
+        assert_eq!(Cursor::new(&buf).read_be::<NutTexture>()?, texture);
+
+        // Header bytes past the padded mipmap size table are kept
+        let mut data = vec![0, 0, 0, 0x74, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0x70, 0, 0];
+        data.extend([0, 2, 0, 14, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([0; 16]);
+        data.extend([0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend(bytes(16));
+        data.extend(b"eXt\0");
+        data.extend([0, 0, 0, 0x20, 0, 0, 0, 0x10, 0, 0, 0, 0]);
+        data.extend(b"GIDX");
+        data.extend([0, 0, 0, 0x10, 0, 0, 0x12, 0x34, 0, 0, 0, 0]);
+        data.extend([1, 2, 3, 4]);
+
+        let texture = Cursor::new(&data).read_be::<NutTexture>()?;
+
+        assert_eq!(texture.mipmaps, vec![2, 2]);
+        assert_eq!(texture.extra_header, bytes(16));
+        assert_eq!(texture.header_size(), 0x70);
+
+        let mut cursor = Cursor::new(Vec::new());
+        texture.write_be(&mut cursor)?;
+        assert_eq!(cursor.into_inner(), data);
+
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ use binrw::binrw;
 use std::hash::Hash;
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Vector3 {
     pub x: f32,
     pub y: f32,
@@ -24,7 +24,7 @@ impl Hash for Vector3 {
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Vector4 {
     pub x: f32,
     pub y: f32,
@@ -48,7 +48,7 @@ impl Hash for Vector4 {
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Vector3Short {
     pub x: i16,
     pub y: i16,
@@ -70,7 +70,7 @@ impl Hash for Vector3Short {
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct VectorShort {
     pub x: i16
 }
@@ -88,28 +88,28 @@ impl Hash for VectorShort {
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct KeyframeVector3 {
     pub frame: i32,
     pub value: Vector3,
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct KeyframeVector4 {
     pub frame: i32,
     pub value: Vector4,
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct KeyframeFloat {
     pub frame: i32,
     pub value: f32,
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct QuaternionShort {
     pub x: i16,
     pub y: i16,
@@ -133,7 +133,7 @@ impl Hash for QuaternionShort {
 }
 
 #[binrw]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -153,3 +153,232 @@ impl Hash for RGB {
         self.b.hash(state);
     }
 }
+
+/// Value of 1.0 in the components of a `QuaternionShort`
+pub const QUATERNION_SHORT_SCALE: f32 = 0x4000 as f32;
+
+/// Value of 1.0 in the components of a `Vector3Short` scale, used by `ScaleShortTable` and `Vector3ShortLinear` curves
+pub const SCALE_SHORT_SCALE: f32 = 0x1000 as f32;
+
+/// Value of 1.0 in a `VectorShort` opacity
+pub const OPACITY_SHORT_SCALE: f32 = 0x4000 as f32;
+
+/// Rounds `value * scale` to the nearest i16, saturating at the limits of i16
+fn to_fixed(value: f32, scale: f32) -> i16 {
+    (value * scale).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+impl Vector4 {
+    /// The quaternion scaled to a length of 1, or the identity rotation if it has no length
+    pub fn normalized(&self) -> Self {
+        let length = (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt();
+
+        if length > 0.0 {
+            Self {
+                x: self.x / length,
+                y: self.y / length,
+                z: self.z / length,
+                w: self.w / length,
+            }
+        } else {
+            Self {
+                w: 1.0,
+                ..Default::default()
+            }
+        }
+    }
+}
+
+impl From<&QuaternionShort> for Vector4 {
+    /// The exact value of each component, without normalizing
+    fn from(quaternion: &QuaternionShort) -> Self {
+        Self {
+            x: quaternion.x as f32 / QUATERNION_SHORT_SCALE,
+            y: quaternion.y as f32 / QUATERNION_SHORT_SCALE,
+            z: quaternion.z as f32 / QUATERNION_SHORT_SCALE,
+            w: quaternion.w as f32 / QUATERNION_SHORT_SCALE,
+        }
+    }
+}
+
+impl From<&Vector4> for QuaternionShort {
+    /// Rounds each component to the nearest fixed-point value, without normalizing
+    fn from(quaternion: &Vector4) -> Self {
+        Self {
+            x: to_fixed(quaternion.x, QUATERNION_SHORT_SCALE),
+            y: to_fixed(quaternion.y, QUATERNION_SHORT_SCALE),
+            z: to_fixed(quaternion.z, QUATERNION_SHORT_SCALE),
+            w: to_fixed(quaternion.w, QUATERNION_SHORT_SCALE),
+        }
+    }
+}
+
+impl QuaternionShort {
+    /// Normalizes a rotation before converting it, since rounding could otherwise overflow i16
+    pub fn from_rotation(rotation: &Vector4) -> Self {
+        Self::from(&rotation.normalized())
+    }
+
+    /// The rotation as a normalized quaternion
+    pub fn rotation(&self) -> Vector4 {
+        Vector4::from(self).normalized()
+    }
+}
+
+impl Vector3Short {
+    /// The vector as a scale, where 0x1000 is 1.0
+    pub fn to_scale(&self) -> Vector3 {
+        Vector3 {
+            x: self.x as f32 / SCALE_SHORT_SCALE,
+            y: self.y as f32 / SCALE_SHORT_SCALE,
+            z: self.z as f32 / SCALE_SHORT_SCALE,
+        }
+    }
+
+    /// Rounds each component of a scale to the nearest fixed-point value, scales beyond about 8.0 saturate
+    pub fn from_scale(scale: &Vector3) -> Self {
+        Self {
+            x: to_fixed(scale.x, SCALE_SHORT_SCALE),
+            y: to_fixed(scale.y, SCALE_SHORT_SCALE),
+            z: to_fixed(scale.z, SCALE_SHORT_SCALE),
+        }
+    }
+}
+
+impl VectorShort {
+    /// The value as an opacity, where 0x4000 is fully opaque
+    pub fn to_opacity(&self) -> f32 {
+        self.x as f32 / OPACITY_SHORT_SCALE
+    }
+
+    pub fn from_opacity(opacity: f32) -> Self {
+        Self {
+            x: to_fixed(opacity, OPACITY_SHORT_SCALE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc_chunk::nucc_chunk_anm::{AnmEntry, Curve, CurveFormat, EntryFormat, Math};
+    use crate::nucc_chunk::{NuccChunkAnm, NuccChunkType};
+    use crate::test_util::*;
+    use crate::Result;
+
+    #[test]
+    fn fixed_point_test() -> Result<()> {
+        // 1.0 is 0x4000 in quaternions and opacities, and 0x1000 in scales
+        let quaternion = QuaternionShort { x: 0, y: -0x2000, z: 0x4000, w: 0x7FFF };
+        assert_eq!(
+            Vector4::from(&quaternion),
+            Vector4 { x: 0.0, y: -0.5, z: 1.0, w: 32767.0 / 16384.0 }
+        );
+        assert_eq!(QuaternionShort::from(&Vector4::from(&quaternion)), quaternion);
+
+        let scale = Vector3Short { x: 0x1000, y: 0x800, z: -0x3000 };
+        assert_eq!(scale.to_scale(), Vector3 { x: 1.0, y: 0.5, z: -3.0 });
+        assert_eq!(Vector3Short::from_scale(&scale.to_scale()), scale);
+
+        assert_eq!(VectorShort { x: 0x2000 }.to_opacity(), 0.5);
+        assert_eq!(VectorShort::from_opacity(0.5), VectorShort { x: 0x2000 });
+
+        // Every fixed-point value converts to a float and back exactly
+        for x in i16::MIN..=i16::MAX {
+            assert_eq!(VectorShort::from_opacity(VectorShort { x }.to_opacity()).x, x);
+            assert_eq!(Vector3Short::from_scale(&Vector3Short { x, y: 0, z: 0 }.to_scale()).x, x);
+        }
+
+        // Rounding goes to the nearest value and saturates at the limits of i16
+        assert_eq!(VectorShort::from_opacity(1.0 / 0x8000 as f32).x, 1);
+        assert_eq!(VectorShort::from_opacity(-3.0).x, i16::MIN);
+        assert_eq!(
+            Vector3Short::from_scale(&Vector3 { x: 100.0, y: -100.0, z: 0.0 }),
+            Vector3Short { x: i16::MAX, y: i16::MIN, z: 0 }
+        );
+
+        // Rotations are normalized, an empty one becomes the identity
+        let rotation = QuaternionShort::from_rotation(&Vector4 { x: 0.0, y: 0.0, z: 3.0, w: 4.0 });
+        assert_eq!(rotation, QuaternionShort { x: 0, y: 0, z: 0x2666, w: 0x3333 });
+
+        let normalized = rotation.rotation();
+        assert_vec_close(&[normalized.x, normalized.y, normalized.z, normalized.w], &[0.0, 0.0, 0.6, 0.8]);
+
+        assert_eq!(
+            QuaternionShort::from_rotation(&Vector4::default()),
+            QuaternionShort { x: 0, y: 0, z: 0, w: 0x4000 }
+        );
+
+        // Fixed-point curves are decoded to floats and encoded back to the same bytes,
+        // Vector3ShortLinear uses the same fixed point as scales
+        let curves = vec![
+            (CurveFormat::OpacityShortTable, vec![Math::Float { channels: 0.25 }]),
+            (CurveFormat::ScaleShortTable, vec![Math::Vec3 { channels: (1.0, 2.0, 0.5) }]),
+            (CurveFormat::QuaternionShortTable, vec![Math::Vec4 { channels: (0.0, 0.0, 0.6, 0.8) }]),
+            (
+                CurveFormat::Vector3ShortLinear,
+                vec![Math::Vec3Linear {
+                    frame: 200,
+                    channels: (1.0, 0.5, -2.0),
+                }],
+            ),
+        ];
+
+        let mut entry = AnmEntry {
+            coord: coord(0, 0),
+            entry_format: EntryFormat::Coord,
+            curve_headers: curves
+                .iter()
+                .enumerate()
+                .map(|(i, (curve_format, _))| {
+                    let size = curve_format.size_per_frame().unwrap();
+                    curve_header(i as u16, curve_format.clone(), 1, size as u16)
+                })
+                .collect(),
+            curves: curves
+                .into_iter()
+                .map(|(curve_format, keyframes)| Curve { curve_format, keyframes })
+                .collect(),
+        };
+
+        let chunk = NuccChunkAnm {
+            version: 121,
+            entries: vec![entry.clone()],
+            ..Default::default()
+        };
+
+        let data = NuccChunkType::write_data(Box::new(chunk), 121)?;
+        let curves_start = data.len() - (4 + 8 + 8 + 8);
+
+        assert_eq!(data[curves_start..curves_start + 2], [0x10, 0x00]);
+        assert_eq!(data[curves_start + 4..curves_start + 10], [0x10, 0x00, 0x20, 0x00, 0x08, 0x00]);
+        assert_eq!(data[curves_start + 12..curves_start + 20], [0x00, 0x00, 0x00, 0x00, 0x26, 0x66, 0x33, 0x33]);
+        assert_eq!(data[curves_start + 20..], [0x00, 0xC8, 0x10, 0x00, 0x08, 0x00, 0xE0, 0x00]);
+
+        let reread = reread_chunk(NuccChunkType::NuccChunkAnm, data)?;
+        let reread = reread.downcast_ref::<NuccChunkAnm>().unwrap();
+        let curves = &reread.entries[0].curves;
+
+        assert_eq!(curves[0].keyframes, vec![Math::Float { channels: 0.25 }]);
+        assert_eq!(curves[1].keyframes, vec![Math::Vec3 { channels: (1.0, 2.0, 0.5) }]);
+        assert!(matches!(curves[2].keyframes[0], Math::Vec4 { channels: (x, y, z, w) }
+            if x == 0.0 && y == 0.0 && (z - 0.6).abs() < 1e-4 && (w - 0.8).abs() < 1e-4));
+        assert_eq!(curves[3], entry.curves[3]);
+
+        // Vector3ShortLinear frames are stored as i16
+        entry.curves[3].keyframes = vec![Math::Vec3Linear {
+            frame: 0x8000,
+            channels: (1.0, 1.0, 1.0),
+        }];
+
+        let chunk = NuccChunkAnm {
+            version: 121,
+            entries: vec![entry],
+            ..Default::default()
+        };
+
+        assert!(NuccChunkType::write_data(Box::new(chunk), 121).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! # Queries
+//! Lookups of structs across every page of an `Xfbin` by name, filepath and chunk type.
+//!
+//! Chunk types are compared as strings, so structs of types that aren't supported (kept as
+//! `NuccUnknown`) can be found by their original type name.
+//!
+use super::nucc::{NuccStruct, NuccStructInfo};
+use super::xfbin::Xfbin;
+
+impl Xfbin {
+    /// Every struct of every page, with the index of its page
+    pub fn structs(&self) -> impl Iterator<Item = (usize, &NuccStructInfo, &dyn NuccStruct)> + '_ {
+        self.pages.iter().enumerate().flat_map(|(page_index, page)| {
+            page.structs
+                .iter()
+                .map(move |nucc_struct| (page_index, nucc_struct.struct_info(), nucc_struct.as_ref()))
+        })
+    }
+
+    pub fn structs_mut(&mut self) -> impl Iterator<Item = (usize, &mut dyn NuccStruct)> + '_ {
+        self.pages.iter_mut().enumerate().flat_map(|(page_index, page)| {
+            page.structs
+                .iter_mut()
+                .map(move |nucc_struct| (page_index, nucc_struct.as_mut()))
+        })
+    }
+
+    pub fn find_by_name<'a>(
+        &'a self,
+        chunk_name: &'a str,
+    ) -> impl Iterator<Item = (usize, &'a NuccStructInfo, &'a dyn NuccStruct)> + 'a {
+        self.structs().filter(move |(_, struct_info, _)| struct_info.chunk_name == chunk_name)
+    }
+
+    /// Finds structs with a filepath matching `pattern`, where `*` matches any number of characters and `?` a single one
+    ///
+    /// Forward slashes in the pattern match the backslashes used in xfbin filepaths
+    pub fn find_by_filepath<'a>(
+        &'a self,
+        pattern: &'a str,
+    ) -> impl Iterator<Item = (usize, &'a NuccStructInfo, &'a dyn NuccStruct)> + 'a {
+        self.structs().filter(move |(_, struct_info, _)| glob_match(pattern, &struct_info.filepath))
+    }
+
+    /// Finds structs by the chunk type name in the file, e.g. "nuccChunkClump"
+    pub fn find_by_type<'a>(
+        &'a self,
+        chunk_type: &'a str,
+    ) -> impl Iterator<Item = (usize, &'a NuccStructInfo, &'a dyn NuccStruct)> + 'a {
+        self.structs().filter(move |(_, struct_info, _)| struct_info.chunk_type == chunk_type)
+    }
+
+    /// The first struct named `chunk_name` that is a `T`
+    pub fn get<T: NuccStruct>(&self, chunk_name: &str) -> Option<&T> {
+        self.structs()
+            .filter(|(_, struct_info, _)| struct_info.chunk_name == chunk_name)
+            .find_map(|(_, _, nucc_struct)| nucc_struct.downcast_ref::<T>())
+    }
+
+    pub fn get_mut<T: NuccStruct>(&mut self, chunk_name: &str) -> Option<&mut T> {
+        self.structs_mut()
+            .filter(|(_, nucc_struct)| nucc_struct.struct_info().chunk_name == chunk_name)
+            .find_map(|(_, nucc_struct)| nucc_struct.downcast_mut::<T>())
+    }
+
+    /// Every struct that is a `T`
+    pub fn all<T: NuccStruct>(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.structs()
+            .filter_map(|(page_index, _, nucc_struct)| nucc_struct.downcast_ref::<T>().map(|s| (page_index, s)))
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let normalize = |c: char| if c == '/' { '\\' } else { c };
+
+    let pattern = pattern.chars().map(normalize).collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    // Position after the last `*` in the pattern and the text position it was tried at
+    let mut backtrack = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc::{NuccBinary, NuccCamera};
+    use crate::nucc_chunk::NuccChunkType;
+    use crate::test_util::*;
+    use crate::{read_xfbin_buf, write_xfbin_buf, Result};
+
+    #[test]
+    fn query_test() -> Result<()> {
+        let mut xfbin = read_xfbin_buf(write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121)), page(fixture_structs(0x79))],
+            ..Default::default()
+        })?)?;
+
+        assert_eq!(xfbin.structs().count(), fixture_structs(121).len() * 2);
+
+        let pages = xfbin.find_by_name("camera").map(|(i, _, _)| i).collect::<Vec<_>>();
+        assert_eq!(pages, vec![0, 1]);
+
+        // Unsupported chunk types are found by the name in the file
+        let clumps = xfbin.find_by_type("nuccChunkClump").collect::<Vec<_>>();
+        assert_eq!(clumps.len(), 2);
+        assert_eq!(clumps[0].2.chunk_type(), NuccChunkType::NuccChunkUnknown);
+
+        let struct_count = xfbin.structs().count();
+
+        for (pattern, count) in [
+            ("c\\test\\anm.max", 2),
+            ("c/test/anm*", 6),
+            ("*.max", struct_count),
+            ("c/test/?amera.max", 2),
+            ("*light*", 4),
+            ("c/test", 0),
+            ("", 0),
+        ] {
+            assert_eq!(xfbin.find_by_filepath(pattern).count(), count, "{}", pattern);
+        }
+
+        assert_eq!(xfbin.get::<NuccCamera>("camera").unwrap().fov, 45.0);
+        assert!(xfbin.get::<NuccBinary>("camera").is_none());
+        assert!(xfbin.get::<NuccCamera>("missing").is_none());
+
+        xfbin.get_mut::<NuccCamera>("camera").unwrap().fov = 60.0;
+
+        let fovs = xfbin.all::<NuccCamera>().map(|(i, c)| (i, c.fov)).collect::<Vec<_>>();
+        assert_eq!(fovs, vec![(0, 60.0), (1, 45.0)]);
+
+        for (_, nucc_struct) in xfbin.structs_mut() {
+            nucc_struct.struct_info_mut().filepath.clear();
+        }
+        assert_eq!(xfbin.find_by_filepath("").count(), struct_count);
+
+        Ok(())
+    }
+}
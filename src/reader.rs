@@ -0,0 +1,351 @@
+//! # Streaming reader
+//! Reads only the header and index of an xfbin up front and records where each chunk is,
+//! so that chunks can be decoded one at a time instead of loading the whole file.
+//!
+use binrw::{binrw, BinReaderExt};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use super::cipher::XfbinCipher;
+use super::error::{Result, XfbinError};
+use super::nucc::*;
+use super::nucc_chunk::{NuccChunk, NuccChunkPage, NuccChunkType};
+use super::xfbin::ReadOptions;
+use super::xfbin_file::{XfbinChunk, XfbinHeader, XfbinIndex, XFBIN_HEADER_SIZE};
+
+/// Location and identity of a chunk, without its data
+#[derive(Debug, Clone, PartialEq)]
+pub struct XfbinChunkDescriptor {
+    pub struct_info: NuccStructInfo,
+    pub page_index: usize,
+
+    /// Offset of the chunk in the file, including its size, map index and version fields
+    pub offset: u64,
+
+    /// Size of the chunk data
+    pub size: u32,
+    pub version: u16,
+}
+
+impl XfbinChunkDescriptor {
+    pub fn chunk_type(&self) -> NuccChunkType {
+        self.struct_info.chunk_type.parse().unwrap_or_default()
+    }
+}
+
+/// The fields that precede the data of an `XfbinChunk`
+#[binrw]
+struct XfbinChunkHeader {
+    size: u32,
+    chunk_map_index: u32,
+
+    #[brw(pad_after = 2)]
+    version: u16,
+}
+
+pub(crate) const CHUNK_HEADER_SIZE: u64 = 12;
+
+pub struct XfbinReader<R: Read + Seek> {
+    reader: CipherReader<R>,
+
+    pub header: XfbinHeader,
+    pub index: XfbinIndex,
+
+    struct_infos: Vec<NuccStructInfo>,
+    struct_references: Vec<NuccStructReference>,
+    chunks: Vec<XfbinChunkDescriptor>,
+
+    /// Index of the first chunk map and reference of each page
+    page_starts: Vec<(usize, usize)>,
+}
+
+impl<R: Read + Seek> XfbinReader<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        Self::with_options(reader, &ReadOptions::default())
+    }
+
+    /// Only the cipher of the options is used, since chunks are decoded individually
+    pub fn with_options(mut reader: R, options: &ReadOptions) -> Result<Self> {
+        let start = reader.stream_position()?;
+        let header = XfbinHeader::read_checked(&mut reader)?;
+
+        let cipher = match (header.encrypted != 0, options.cipher.as_ref()) {
+            (true, None) => return Err(XfbinError::MissingCipher),
+            (true, cipher) => cipher.cloned(),
+            (false, _) => None,
+        };
+
+        let mut reader = CipherReader {
+            inner: reader,
+            cipher,
+            encrypted_start: start + XFBIN_HEADER_SIZE as u64,
+        };
+
+        let index = reader
+            .read_be::<XfbinIndex>()
+            .map_err(XfbinError::TruncatedIndex)?;
+
+        let chunks_start = reader.stream_position()?;
+
+        let struct_infos = Vec::<NuccStructInfo>::try_from(XfbinChunkMapConverter {
+            chunk_maps: index.chunk_maps.clone(),
+            chunk_names: index.chunk_names.iter().map(|s| s.to_string()).collect(),
+            chunk_types: index.chunk_types.iter().map(|s| s.to_string()).collect(),
+            filepaths: index.filepaths.iter().map(|s| s.to_string()).collect(),
+        })?;
+
+        let struct_references = Vec::<NuccStructReference>::try_from(XfbinChunkReferenceConverter {
+            references: index.chunk_references.clone(),
+            chunk_names: index.chunk_names.iter().map(|s| s.to_string()).collect(),
+            struct_infos: struct_infos.clone(),
+        })?;
+
+        let struct_infos = index
+            .chunk_map_indices
+            .iter()
+            .map(|&i| {
+                struct_infos
+                    .get(i as usize)
+                    .cloned()
+                    .ok_or_else(|| XfbinError::out_of_range("chunk map", i as usize, struct_infos.len()))
+            })
+            .collect::<Result<Vec<NuccStructInfo>>>()?;
+
+        let mut xfbin_reader = Self {
+            reader,
+            header,
+            index,
+            struct_infos,
+            struct_references,
+            chunks: Vec::new(),
+            page_starts: vec![(0, 0)],
+        };
+
+        xfbin_reader.scan_chunks(chunks_start)?;
+
+        Ok(xfbin_reader)
+    }
+
+    /// Walks the chunk headers, only reading the data of page chunks to follow the page boundaries
+    fn scan_chunks(&mut self, chunks_start: u64) -> Result<()> {
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        let mut offset = self.reader.seek(SeekFrom::Start(chunks_start))?;
+
+        let mut struct_infos_index = 0;
+        let mut struct_references_index = 0;
+        let mut page_index = 0;
+
+        while offset < end {
+            let chunk_header = self.reader.read_be::<XfbinChunkHeader>()?;
+
+            let mapped_index = struct_infos_index + chunk_header.chunk_map_index as usize;
+
+            let struct_info = self
+                .struct_infos
+                .get(mapped_index)
+                .cloned()
+                .ok_or_else(|| XfbinError::out_of_range("chunk map index", mapped_index, self.struct_infos.len()))?;
+
+            let descriptor = XfbinChunkDescriptor {
+                struct_info,
+                page_index,
+                offset,
+                size: chunk_header.size,
+                version: chunk_header.version,
+            };
+
+            if descriptor.chunk_type() == NuccChunkType::NuccChunkPage {
+                let page = self
+                    .read_chunk(&descriptor)?
+                    .downcast::<NuccChunkPage>()
+                    .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkPage>(c.chunk_type()))?;
+
+                struct_infos_index += page.map_index_count as usize;
+                struct_references_index += page.reference_count as usize;
+                page_index += 1;
+
+                self.page_starts.push((struct_infos_index, struct_references_index));
+            }
+
+            self.chunks.push(descriptor);
+
+            offset = self
+                .reader
+                .seek(SeekFrom::Start(offset + CHUNK_HEADER_SIZE + chunk_header.size as u64))?;
+        }
+
+        Ok(())
+    }
+
+    /// Descriptors of every chunk in the file, including the null and page chunks
+    pub fn chunks(&self) -> &[XfbinChunkDescriptor] {
+        &self.chunks
+    }
+
+    /// Descriptors of the chunks that can be decoded into structs
+    pub fn struct_chunks(&self) -> impl Iterator<Item = &XfbinChunkDescriptor> {
+        self.chunks.iter().filter(|descriptor| {
+            !matches!(
+                descriptor.chunk_type(),
+                NuccChunkType::NuccChunkNull | NuccChunkType::NuccChunkPage
+            )
+        })
+    }
+
+    /// Reads the raw chunk described by `descriptor`
+    pub fn read_raw_chunk(&mut self, descriptor: &XfbinChunkDescriptor) -> Result<XfbinChunk> {
+        self.reader.seek(SeekFrom::Start(descriptor.offset))?;
+
+        Ok(self.reader.read_be::<XfbinChunk>()?)
+    }
+
+    pub fn read_chunk(&mut self, descriptor: &XfbinChunkDescriptor) -> Result<Box<dyn NuccChunk>> {
+        self.read_raw_chunk(descriptor)?.unpack(&descriptor.struct_info)
+    }
+
+    pub fn read_struct(&mut self, descriptor: &XfbinChunkDescriptor) -> Result<Box<dyn NuccStruct>> {
+        let nucc_chunk = self.read_chunk(descriptor)?;
+
+        let (struct_infos_index, struct_references_index) = self
+            .page_starts
+            .get(descriptor.page_index)
+            .copied()
+            .ok_or_else(|| XfbinError::out_of_range("page", descriptor.page_index, self.page_starts.len()))?;
+
+        let mut nucc_struct = Box::<dyn NuccStruct>::try_from(NuccStructConverter {
+            nucc_chunk,
+            struct_infos: self.struct_infos.get(struct_infos_index..).unwrap_or_default(),
+            struct_references: self.struct_references.get(struct_references_index..).unwrap_or_default(),
+        })?;
+
+        *nucc_struct.struct_info_mut() = descriptor.struct_info.clone();
+
+        Ok(nucc_struct)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader.inner
+    }
+
+    pub(crate) fn into_parts(self) -> (XfbinHeader, XfbinIndex, Vec<XfbinChunkDescriptor>) {
+        (self.header, self.index, self.chunks)
+    }
+}
+
+/// Decrypts everything read past the header when a cipher is set
+struct CipherReader<R> {
+    inner: R,
+    cipher: Option<Arc<dyn XfbinCipher>>,
+    encrypted_start: u64,
+}
+
+impl<R: Read + Seek> Read for CipherReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.inner.stream_position()?;
+        let read = self.inner.read(buf)?;
+
+        if let Some(cipher) = &self.cipher {
+            let skip = self.encrypted_start.saturating_sub(position).min(read as u64) as usize;
+            let offset = (position + skip as u64).saturating_sub(self.encrypted_start);
+
+            cipher.decrypt(&mut buf[skip..read], offset);
+        }
+
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for CipherReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::XorCipher;
+    use crate::test_util::*;
+    use crate::xfbin::{WriteOptions, Xfbin};
+    use crate::{read_xfbin_buf, write_xfbin_buf, write_xfbin_buf_with};
+    use std::io::Cursor;
+
+    #[test]
+    fn xfbin_reader_test() -> Result<()> {
+        for (name, xfbin) in fixtures() {
+            let page_count = xfbin.pages.len();
+            let buf = write_xfbin_buf(xfbin)?;
+            let expected = read_xfbin_buf(buf.clone())?;
+
+            let mut reader = XfbinReader::new(Cursor::new(buf.clone()))?;
+
+            let descriptors = reader.struct_chunks().cloned().collect::<Vec<_>>();
+            let expected_structs = expected
+                .pages
+                .iter()
+                .enumerate()
+                .flat_map(|(i, page)| page.structs.iter().map(move |s| (i, s)))
+                .collect::<Vec<_>>();
+
+            assert_eq!(descriptors.len(), expected_structs.len(), "{}", name);
+
+            let page_chunks = reader
+                .chunks()
+                .iter()
+                .filter(|d| d.chunk_type() == NuccChunkType::NuccChunkPage)
+                .count();
+            assert_eq!(page_chunks, page_count, "{}", name);
+
+            for (descriptor, (page_index, expected_struct)) in descriptors.iter().zip(expected_structs) {
+                assert_eq!(descriptor.page_index, page_index, "{}", name);
+                assert_eq!(&descriptor.struct_info, expected_struct.struct_info(), "{}", name);
+
+                // The descriptor points at the chunk in the original buffer
+                let offset = descriptor.offset as usize;
+                assert_eq!(buf[offset..offset + 4], descriptor.size.to_be_bytes(), "{}", name);
+
+                let nucc_struct = reader.read_struct(descriptor)?;
+                assert_eq!(nucc_struct.chunk_type(), expected_struct.chunk_type(), "{}", name);
+                assert_eq!(nucc_struct.version(), expected_struct.version(), "{}", name);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn xfbin_reader_encrypted_test() -> Result<()> {
+        let cipher: Arc<dyn XfbinCipher> = Arc::new(XorCipher::new(*b"stream"));
+
+        let buf = write_xfbin_buf_with(
+            Xfbin {
+                encrypted: true,
+                pages: vec![page(fixture_structs(121))],
+                ..Default::default()
+            },
+            &WriteOptions {
+                cipher: Some(cipher.clone()),
+            },
+        )?;
+
+        assert!(matches!(
+            XfbinReader::new(Cursor::new(buf.clone())),
+            Err(XfbinError::MissingCipher)
+        ));
+
+        let mut reader = XfbinReader::with_options(
+            Cursor::new(buf),
+            &ReadOptions {
+                cipher: Some(cipher),
+                ..Default::default()
+            },
+        )?;
+
+        let descriptor = reader.struct_chunks().next().cloned().unwrap();
+        let binary = reader.read_struct(&descriptor)?;
+
+        assert_eq!(binary.downcast_ref::<NuccBinary>().unwrap().data, bytes(37));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+//! # Round-trip verification
+//! Reads an xfbin, writes it back and reports the first offset where the output
+//! differs from the original, along with the part of the file it falls in.
+//!
+use binrw::io::Cursor;
+use std::mem::size_of;
+
+use super::error::{Result, XfbinError};
+use super::nucc::{NuccStructInfo, XfbinChunkMapConverter};
+use super::nucc_chunk::{NuccChunkPage, NuccChunkType};
+use super::xfbin::{ReadOptions, WriteOptions};
+use super::xfbin_file::{XfbinFile, XfbinHeader, XFBIN_HEADER_SIZE};
+use super::{read_xfbin_buf_with, write_xfbin_buf_with};
+
+/// Size of the fields that precede the data of an `XfbinChunk`
+const CHUNK_HEADER_SIZE: usize = size_of::<u32>() * 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundTripReport {
+    pub original_size: usize,
+    pub written_size: usize,
+    pub mismatch: Option<RoundTripMismatch>,
+}
+
+impl RoundTripReport {
+    pub fn is_exact(&self) -> bool {
+        self.mismatch.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundTripMismatch {
+    /// Offset of the first byte that differs, or the length of the shorter buffer
+    pub offset: usize,
+    pub region: RoundTripRegion,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoundTripRegion {
+    Header,
+    Index,
+    Chunk {
+        chunk_index: usize,
+        chunk_offset: u64,
+        struct_info: NuccStructInfo,
+    },
+
+    /// Past the end of the last chunk of the original file
+    Trailing,
+}
+
+pub fn verify_roundtrip(buf: &[u8]) -> Result<RoundTripReport> {
+    verify_roundtrip_with(buf, &ReadOptions::default())
+}
+
+/// Verifies the round trip of a file that needs read options, such as an encrypted one
+///
+/// The cipher of the options is used to write the file back as well
+pub fn verify_roundtrip_with(buf: &[u8], options: &ReadOptions) -> Result<RoundTripReport> {
+    let (xfbin, _) = read_xfbin_buf_with(buf.to_vec(), options)?;
+    let written = write_xfbin_buf_with(
+        xfbin,
+        &WriteOptions {
+            cipher: options.cipher.clone(),
+        },
+    )?;
+
+    let mismatch = buf
+        .iter()
+        .zip(written.iter())
+        .position(|(a, b)| a != b)
+        .or_else(|| (buf.len() != written.len()).then(|| buf.len().min(written.len())))
+        .map(|offset| -> Result<RoundTripMismatch> {
+            Ok(RoundTripMismatch {
+                offset,
+                region: find_region(buf, offset, options)?,
+            })
+        })
+        .transpose()?;
+
+    Ok(RoundTripReport {
+        original_size: buf.len(),
+        written_size: written.len(),
+        mismatch,
+    })
+}
+
+fn find_region(buf: &[u8], offset: usize, options: &ReadOptions) -> Result<RoundTripRegion> {
+    if offset < XFBIN_HEADER_SIZE {
+        return Ok(RoundTripRegion::Header);
+    }
+
+    // The regions are found in the decrypted file, which has the same layout
+    let mut buf = buf.to_vec();
+
+    if XfbinHeader::read_checked(&mut Cursor::new(&buf))?.encrypted != 0 {
+        let cipher = options.cipher.as_ref().ok_or(XfbinError::MissingCipher)?;
+        cipher.decrypt(&mut buf[XFBIN_HEADER_SIZE..], 0);
+    }
+
+    let xfbin_file = XfbinFile::read(&mut Cursor::new(&buf))?;
+
+    let chunks_start = xfbin_file
+        .chunks
+        .first()
+        .map_or(buf.len(), |chunk| chunk.offset as usize);
+
+    if offset < chunks_start {
+        return Ok(RoundTripRegion::Index);
+    }
+
+    let struct_infos = chunk_struct_infos(&xfbin_file)?;
+
+    Ok(xfbin_file
+        .chunks
+        .iter()
+        .zip(struct_infos)
+        .enumerate()
+        .find(|(_, (chunk, _))| offset < chunk.offset as usize + CHUNK_HEADER_SIZE + chunk.data.len())
+        .map_or(RoundTripRegion::Trailing, |(chunk_index, (chunk, struct_info))| {
+            RoundTripRegion::Chunk {
+                chunk_index,
+                chunk_offset: chunk.offset,
+                struct_info,
+            }
+        }))
+}
+
+/// Resolves the struct info of every chunk, following the page boundaries the same way the reader does
+fn chunk_struct_infos(xfbin_file: &XfbinFile) -> Result<Vec<NuccStructInfo>> {
+    let struct_infos = Vec::<NuccStructInfo>::try_from(XfbinChunkMapConverter {
+        chunk_maps: xfbin_file.index.chunk_maps.clone(),
+        chunk_names: xfbin_file.index.chunk_names.iter().map(|s| s.to_string()).collect(),
+        chunk_types: xfbin_file.index.chunk_types.iter().map(|s| s.to_string()).collect(),
+        filepaths: xfbin_file.index.filepaths.iter().map(|s| s.to_string()).collect(),
+    })?;
+
+    let mut struct_infos_index = 0;
+
+    xfbin_file
+        .chunks
+        .iter()
+        .map(|chunk| {
+            let mapped_index = struct_infos_index + chunk.chunk_map_index as usize;
+
+            let struct_info = xfbin_file
+                .index
+                .chunk_map_indices
+                .get(mapped_index)
+                .and_then(|&i| struct_infos.get(i as usize))
+                .cloned()
+                .ok_or_else(|| {
+                    XfbinError::out_of_range("chunk map index", mapped_index, xfbin_file.index.chunk_map_indices.len())
+                })?;
+
+            if struct_info.chunk_type == NuccChunkType::NuccChunkPage.to_string() {
+                let page = chunk
+                    .clone()
+                    .unpack(&struct_info)?
+                    .downcast::<NuccChunkPage>()
+                    .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkPage>(c.chunk_type()))?;
+
+                struct_infos_index += page.map_index_count as usize;
+            }
+
+            Ok(struct_info)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc::{NuccAnmStrm, NuccBinary, NuccCamera, NuccMorphModel, NuccTexture};
+    use crate::nucc_chunk::{NuccChunkIndex, NuccChunkNull};
+    use crate::test_util::*;
+    use crate::xfbin::{Xfbin, XfbinPage};
+    use crate::cipher::{XfbinCipher, XorCipher};
+    use crate::xfbin_file::{XfbinChunk, XfbinChunkMap, XfbinIndex};
+    use crate::{read_xfbin_buf, write_xfbin_buf};
+    use binrw::{BinWrite, NullString};
+    use std::sync::Arc;
+
+    #[test]
+    fn roundtrip_fixtures_test() -> Result<()> {
+        for (name, xfbin) in fixtures() {
+            let buf = write_xfbin_buf(xfbin)?;
+
+            let report = verify_roundtrip(&buf)?;
+            assert!(report.is_exact(), "{}: {:?}", name, report);
+            assert_eq!(report.original_size, buf.len(), "{}", name);
+
+            assert_eq!(write_xfbin_buf(read_xfbin_buf(buf.clone())?)?, buf, "{}", name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_padded_chunk_maps_test() -> Result<()> {
+        let names = |names: &[&str]| names.iter().map(|&name| NullString::from(name)).collect::<Vec<_>>();
+        let chunk_map = |chunk_type_index, filepath_index, chunk_name_index| XfbinChunkMap {
+            chunk_type_index,
+            filepath_index,
+            chunk_name_index,
+        };
+        let chunk = |chunk_map_index, data: Vec<u8>| XfbinChunk {
+            chunk_map_index,
+            version: 121,
+            data,
+            ..Default::default()
+        };
+        let page_chunk = |chunk_map_index, map_index_count: u32| {
+            chunk(chunk_map_index, [map_index_count.to_be_bytes(), 0u32.to_be_bytes()].concat())
+        };
+
+        // An empty page whose 3 chunk maps are padded to 4 with the null map, followed by a page that
+        // maps its binary chunk twice
+        let xfbin_file = XfbinFile {
+            header: XfbinHeader {
+                version: 121,
+                encrypted: 0,
+            },
+            index: XfbinIndex {
+                min_page_size: 2,
+                version: 121,
+                chunk_types: names(&["nuccChunkNull", "nuccChunkPage", "nuccChunkIndex", "nuccChunkBinary"]),
+                filepaths: names(&["", "c\\test\\binary.max"]),
+                chunk_names: names(&["", "Page0", "index", "binary"]),
+                chunk_maps: vec![chunk_map(0, 0, 0), chunk_map(1, 0, 1), chunk_map(2, 0, 2), chunk_map(3, 1, 3)],
+                chunk_references: Vec::new(),
+                chunk_map_indices: vec![0, 1, 2, 0, 0, 3, 3, 1, 2],
+            },
+            chunks: vec![
+                chunk(0, Vec::new()),
+                chunk(0, Vec::new()),
+                page_chunk(1, 4),
+                chunk(0, Vec::new()),
+                chunk(1, [8u32.to_be_bytes().to_vec(), bytes(8)].concat()),
+                page_chunk(3, 5),
+            ],
+        };
+
+        let mut cursor = Cursor::new(Vec::new());
+        xfbin_file.write_be(&mut cursor)?;
+        let buf = cursor.into_inner();
+
+        let xfbin = read_xfbin_buf(buf.clone())?;
+
+        let null_info = NuccChunkNull::default_chunk_info();
+        let page_info = NuccChunkPage::default_chunk_info();
+        let index_info = NuccChunkIndex::default_chunk_info();
+        let binary_info = struct_info("binary", NuccChunkType::NuccChunkBinary);
+
+        assert_eq!(xfbin.pages.len(), 2);
+        assert_eq!(
+            xfbin.pages[0].struct_infos,
+            vec![null_info.clone(), page_info.clone(), index_info.clone(), null_info.clone()]
+        );
+        assert_eq!(
+            xfbin.pages[1].struct_infos,
+            vec![null_info, binary_info.clone(), binary_info, page_info, index_info]
+        );
+
+        assert_eq!(write_xfbin_buf(xfbin)?, buf);
+        assert!(verify_roundtrip(&buf)?.is_exact());
+
+        // Pages that are built from scratch are padded the same way
+        let mut xfbin = read_xfbin_buf(buf)?;
+        xfbin.pages.truncate(1);
+
+        let empty = write_xfbin_buf(Xfbin {
+            pages: vec![XfbinPage::default()],
+            ..Default::default()
+        })?;
+        assert_eq!(write_xfbin_buf(xfbin)?, empty);
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_preserves_structs_test() -> Result<()> {
+        let buf = write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        })?;
+
+        let xfbin = read_xfbin_buf(buf)?;
+        let page = &xfbin.pages[0];
+
+        let expected = fixture_structs(121)
+            .iter()
+            .map(|s| s.struct_info().clone())
+            .collect::<Vec<_>>();
+
+        let actual = page
+            .structs
+            .iter()
+            .map(|s| s.struct_info().clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(actual, expected);
+
+        // The chunk maps of the page are the null chunk, the structs, and the page and index chunks
+        assert_eq!(page.struct_infos.len(), expected.len() + 3);
+        assert_eq!(page.struct_infos[0], NuccChunkNull::default_chunk_info());
+        assert_eq!(page.struct_infos[1..=expected.len()], expected[..]);
+
+        let binary = page.structs[0].downcast_ref::<NuccBinary>().unwrap();
+        assert_eq!(binary.data, bytes(37));
+
+        let anmstrm = page.structs[2].downcast_ref::<NuccAnmStrm>().unwrap();
+        assert_eq!(anmstrm.other_entry_indices, vec![5, 6, 7]);
+        assert_eq!(anmstrm.unk_entry_count, 1);
+        assert_eq!(anmstrm.frame_size, 50);
+
+        let morphmodel = page
+            .structs
+            .iter()
+            .find_map(|s| s.downcast_ref::<NuccMorphModel>())
+            .unwrap();
+        assert!(morphmodel.targets.is_empty());
+        assert_eq!(morphmodel.target_reference_index, 3);
+
+        let camera = page
+            .structs
+            .iter()
+            .find_map(|s| s.downcast_ref::<NuccCamera>())
+            .unwrap();
+        assert_eq!(camera.field00, 0xDEADBEEF);
+        assert_eq!(camera.fov, 45.0);
+        assert_eq!(camera.extra, bytes(8));
+
+        let texture = page
+            .structs
+            .iter()
+            .find_map(|s| s.downcast_ref::<NuccTexture>())
+            .unwrap();
+        assert_eq!(texture.nut.textures.len(), 2);
+        assert_eq!(texture.nut.textures[0].mipmaps, vec![32, 8, 8]);
+        assert_eq!(texture.nut.textures[1].hash, 0x5678);
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_preserves_versions_test() -> Result<()> {
+        let buf = write_xfbin_buf(Xfbin {
+            header_version: 0x63,
+            index_version: 0x64,
+            version: 0x65,
+            pages: vec![page(fixture_structs(0x66)), page(fixture_structs(0x79))],
+            ..Default::default()
+        })?;
+
+        let xfbin_file = XfbinFile::read(&mut Cursor::new(&buf))?;
+        assert_eq!(xfbin_file.header.version, 0x63);
+        assert_eq!(xfbin_file.index.version, 0x64);
+        assert_eq!(xfbin_file.chunks[0].version, 0x65);
+
+        let xfbin = read_xfbin_buf(buf)?;
+
+        assert_eq!(xfbin.header_version, 0x63);
+        assert_eq!(xfbin.index_version, 0x64);
+        assert_eq!(xfbin.version, 0x65);
+        assert!(xfbin.pages[0].structs.iter().all(|s| s.version() == 0x66));
+        assert!(xfbin.pages[1].structs.iter().all(|s| s.version() == 0x79));
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_roundtrip_encrypted_test() -> Result<()> {
+        let options = ReadOptions {
+            cipher: Some(Arc::new(XorCipher::new(*b"roundtrip")) as Arc<dyn XfbinCipher>),
+            ..Default::default()
+        };
+
+        let xfbin = Xfbin {
+            encrypted: true,
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        };
+
+        let mut xfbin_file = XfbinFile::try_from(xfbin)?;
+        let mut cursor = Cursor::new(Vec::new());
+        xfbin_file.write_be(&mut cursor)?;
+        let mut buf = cursor.into_inner();
+        options.cipher.as_ref().unwrap().encrypt(&mut buf[XFBIN_HEADER_SIZE..], 0);
+
+        assert!(matches!(verify_roundtrip(&buf), Err(XfbinError::MissingCipher)));
+
+        let report = verify_roundtrip_with(&buf, &options)?;
+        assert!(report.is_exact(), "{:?}", report);
+
+        // Mismatches are found in the decrypted chunks, here a trailing byte that's dropped on write
+        let binary_chunk = xfbin_file.chunks.iter_mut().find(|c| c.data.len() == 4 + 37).unwrap();
+        binary_chunk.data.push(0xFF);
+
+        let mut cursor = Cursor::new(Vec::new());
+        xfbin_file.write_be(&mut cursor)?;
+        let mut buf = cursor.into_inner();
+        options.cipher.as_ref().unwrap().encrypt(&mut buf[XFBIN_HEADER_SIZE..], 0);
+
+        let report = verify_roundtrip_with(&buf, &options)?;
+        assert_eq!(report.written_size, report.original_size - 1);
+
+        match report.mismatch.map(|mismatch| mismatch.region) {
+            Some(RoundTripRegion::Chunk { struct_info: chunk_struct_info, .. }) => {
+                assert_eq!(chunk_struct_info, struct_info("binary", NuccChunkType::NuccChunkBinary))
+            }
+            region => panic!("Unexpected region {:?}", region),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_roundtrip_mismatch_test() -> Result<()> {
+        let xfbin = Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        };
+
+        // Trailing bytes after the binary payload are not part of the chunk and are dropped on write
+        let mut xfbin_file = XfbinFile::try_from(xfbin)?;
+        let binary_chunk = xfbin_file.chunks.iter_mut().find(|c| c.data.len() == 4 + 37).unwrap();
+        binary_chunk.data.push(0xFF);
+
+        let mut cursor = Cursor::new(Vec::new());
+        xfbin_file.write_be(&mut cursor)?;
+        let buf = cursor.into_inner();
+
+        let report = verify_roundtrip(&buf)?;
+        let mismatch = report.mismatch.unwrap();
+
+        assert_eq!(report.written_size, report.original_size - 1);
+
+        match mismatch.region {
+            RoundTripRegion::Chunk {
+                chunk_offset,
+                struct_info: chunk_struct_info,
+                ..
+            } => {
+                assert_eq!(chunk_struct_info, struct_info("binary", NuccChunkType::NuccChunkBinary));
+                // The size field of the chunk is the first thing to differ
+                assert_eq!(mismatch.offset as u64, chunk_offset + 3);
+            }
+            region => panic!("Unexpected region {:?}", region),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+//! Fixtures and helpers shared by the tests of more than one module
+//!
+use crate::nucc::*;
+use crate::nucc_chunk::nucc_chunk_anm::{AnmCoord, AnmEntry, CoordParent, Curve, CurveFormat, CurveHeader, EntryFormat, Math};
+use crate::nucc_chunk::nucc_chunk_anmstrm::AnmStrmFrameInfo;
+use crate::nucc_chunk::nucc_chunk_anmstrmframe::{AnmEntryBone, AnmStrmEntry, Entry};
+use crate::nucc_chunk::nucc_chunk_texture::{Nut, NutTexture};
+use crate::nucc_chunk::nucc_helper::{self, Vector3, Vector4};
+use crate::nucc_chunk::{NuccChunk, NuccChunkType};
+use crate::xfbin::{Xfbin, XfbinPage};
+use crate::Result;
+
+pub fn struct_info(chunk_name: &str, chunk_type: NuccChunkType) -> NuccStructInfo {
+    NuccStructInfo {
+        chunk_name: String::from(chunk_name),
+        filepath: format!("c\\test\\{}.max", chunk_name),
+        chunk_type: chunk_type.to_string(),
+    }
+}
+
+pub fn bytes(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+pub fn page(structs: Vec<Box<dyn NuccStruct>>) -> XfbinPage {
+    XfbinPage {
+        structs,
+        ..Default::default()
+    }
+}
+
+/// Reads a chunk from `data` and checks that writing it back gives the same bytes
+pub fn reread_chunk(chunk_type: NuccChunkType, data: Vec<u8>) -> Result<Box<dyn NuccChunk>> {
+    let chunk = NuccChunkType::read_data(&data, &chunk_type.to_string(), 121)?;
+    let written = NuccChunkType::write_data(chunk, 121)?;
+    assert_eq!(written, data);
+
+    NuccChunkType::read_data(&written, &chunk_type.to_string(), 121)
+}
+
+pub fn coord(clump_index: i16, coord_index: u16) -> AnmCoord {
+    AnmCoord {
+        clump_index,
+        coord_index,
+    }
+}
+
+pub fn curve_header(curve_index: u16, curve_format: CurveFormat, frame_count: u16, curve_size: u16) -> CurveHeader {
+    CurveHeader {
+        curve_index,
+        curve_format,
+        frame_count,
+        curve_size,
+    }
+}
+
+/// An opacity curve that needs padding, followed by a location curve
+pub fn fixture_curves() -> Vec<Curve> {
+    vec![
+        Curve {
+            curve_format: CurveFormat::OpacityShortTable,
+            keyframes: [0x3FFF, 0x2000, 0]
+                .into_iter()
+                .map(|opacity| Math::Float {
+                    channels: opacity as f32 / nucc_helper::OPACITY_SHORT_SCALE,
+                })
+                .collect(),
+        },
+        Curve {
+            curve_format: CurveFormat::Vector3Linear,
+            keyframes: vec![
+                Math::Vec3Linear {
+                    frame: 0,
+                    channels: (0.0, 1.0, 2.0),
+                },
+                Math::Vec3Linear {
+                    frame: 100,
+                    channels: (3.0, 4.0, 5.0),
+                },
+            ],
+        },
+    ]
+}
+
+pub fn clump_info() -> NuccStructInfo {
+    NuccStructInfo {
+        chunk_name: String::from("clump"),
+        filepath: String::from("c\\test\\clump.max"),
+        chunk_type: String::from("nuccChunkClump"),
+    }
+}
+
+/// One struct of every supported chunk type
+pub fn fixture_structs(version: u16) -> Vec<Box<dyn NuccStruct>> {
+    vec![
+        Box::new(NuccBinary {
+            struct_info: struct_info("binary", NuccChunkType::NuccChunkBinary),
+            version,
+            data: bytes(37),
+        }),
+        Box::new(NuccAnm {
+            struct_info: struct_info("anm", NuccChunkType::NuccChunkAnm),
+            version,
+            frame_count: 3000,
+            frame_size: 100,
+            is_looped: true,
+            clumps: vec![NuccAnmClump {
+                clump: clump_info(),
+                bone_materials: vec![struct_info("binary", NuccChunkType::NuccChunkBinary), clump_info()],
+                models: vec![struct_info("binary", NuccChunkType::NuccChunkBinary)],
+            }],
+            other_entries_indices: vec![5],
+            unk_entry_indices: vec![],
+            coord_parents: vec![CoordParent {
+                parent: coord(0, 0),
+                child: coord(0, 1),
+            }],
+            entries: vec![AnmEntry {
+                coord: coord(0, 1),
+                entry_format: EntryFormat::Coord,
+                curve_headers: vec![
+                    curve_header(0, CurveFormat::OpacityShortTable, 3, 6),
+                    curve_header(1, CurveFormat::Vector3Linear, 2, 32),
+                ],
+                curves: fixture_curves(),
+            }],
+        }),
+        Box::new(NuccAnmStrm {
+            struct_info: struct_info("anmstrm", NuccChunkType::NuccChunkAnmStrm),
+            version,
+            frame_count: 200,
+            // Not every file uses the usual frame size
+            frame_size: 50,
+            is_looped: false,
+            clumps: vec![NuccAnmClump {
+                clump: clump_info(),
+                bone_materials: vec![struct_info("binary", NuccChunkType::NuccChunkBinary)],
+                models: vec![clump_info(), struct_info("binary", NuccChunkType::NuccChunkBinary)],
+            }],
+            other_entry_indices: vec![5, 6, 7],
+            unk_entry_count: 1,
+            coord_parents: vec![],
+            entries: vec![AnmStrmFrameInfo {
+                unknown: 0,
+                frame_offset: 0,
+                frame_number: 0,
+            }],
+        }),
+        Box::new(NuccAnmStrmFrame {
+            struct_info: struct_info("anmstrmframe", NuccChunkType::NuccChunkAnmStrmFrame),
+            version,
+            frame_number: 100,
+            unknown: 0,
+            entries: vec![AnmStrmEntry {
+                coord: coord(0, 2),
+                entry_format: 1,
+                entry_size: 48,
+                entry_data: Entry::Bone(AnmEntryBone {
+                    frame_count: 1,
+                    location: Vector3 { x: 1.0, y: 2.0, z: 3.0 },
+                    rotation: Vector4 { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+                    scale: Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+                    toggled: 1.0,
+                }),
+            }],
+        }),
+        Box::new(NuccCamera {
+            struct_info: struct_info("camera", NuccChunkType::NuccChunkCamera),
+            version,
+            field00: 0xDEADBEEF,
+            fov: 45.0,
+            extra: bytes(8),
+        }),
+        Box::new(NuccLightDirc {
+            struct_info: struct_info("lightdirc", NuccChunkType::NuccChunkLightDirc),
+            version,
+            color: Vector3 { x: 1.0, y: 0.9, z: 0.8 },
+            intensity: 1.5,
+            direction: Vector4 { x: 0.0, y: 0.0, z: 0.6, w: 0.8 },
+            flags: 1,
+            unk: [0x11; 28],
+        }),
+        Box::new(NuccLightPoint {
+            struct_info: struct_info("lightpoint", NuccChunkType::NuccChunkLightPoint),
+            version,
+            color: Vector3 { x: 1.0, y: 0.5, z: 0.0 },
+            position: Vector3 { x: 10.0, y: 20.0, z: -5.0 },
+            intensity: 2.0,
+            radius: 50.0,
+            falloff: 0.5,
+            flags: 0,
+            unk: [0x22; 24],
+        }),
+        Box::new(NuccLayerSet {
+            struct_info: struct_info("layerset", NuccChunkType::NuccChunkLayerSet),
+            version,
+            field00: 1,
+            layers: [struct_info("binary", NuccChunkType::NuccChunkBinary), clump_info()]
+                .into_iter()
+                .enumerate()
+                .map(|(i, chunk)| NuccLayerSetLayer {
+                    chunk: Some(chunk),
+                    chunk_index: 0,
+                    flags: 1 << i,
+                    field08: 0,
+                    field0c: 0,
+                })
+                .collect(),
+            extra: Vec::new(),
+        }),
+        Box::new(NuccAmbient {
+            struct_info: struct_info("ambient", NuccChunkType::NuccChunkAmbient),
+            version,
+            color: Vector3 { x: 0.2, y: 0.3, z: 0.4 },
+            intensity: 0.5,
+        }),
+        Box::new(NuccMorphModel {
+            struct_info: struct_info("morphmodel", NuccChunkType::NuccChunkMorphModel),
+            version,
+            flags: 1,
+            clump: clump_info(),
+            model: struct_info("binary", NuccChunkType::NuccChunkBinary),
+            targets: Vec::new(),
+            // Kept as it is since there are no targets to take it from
+            target_reference_index: 3,
+            field14: 0,
+            field18: 0,
+        }),
+        Box::new(NuccTexture {
+            struct_info: struct_info("texture", NuccChunkType::NuccChunkTexture),
+            version,
+            field00: 0,
+            width: 8,
+            height: 8,
+            field06: 0,
+            nut: Nut {
+                version: 0x100,
+                textures: vec![
+                    NutTexture {
+                        mipmap_count: 3,
+                        pixel_format: 0,
+                        width: 8,
+                        height: 8,
+                        mipmaps: vec![32, 8, 8],
+                        hash: 0x1234,
+                        texture_data: bytes(48),
+                        ..Default::default()
+                    },
+                    NutTexture {
+                        pixel_format: 14,
+                        width: 2,
+                        height: 2,
+                        hash: 0x5678,
+                        texture_data: bytes(16),
+                        ..Default::default()
+                    },
+                ],
+            },
+        }),
+        Box::new(NuccUnknown {
+            struct_info: clump_info(),
+            version,
+            data: bytes(20),
+            chunk_type: String::from("nuccChunkClump"),
+        }),
+    ]
+}
+
+pub fn fixtures() -> Vec<(&'static str, Xfbin)> {
+    let mut referencing_page = page(fixture_structs(121));
+    referencing_page.struct_references.push(NuccStructReference {
+        chunk_name: String::from("clump_ref"),
+        struct_info: referencing_page.structs.last().unwrap().struct_info().clone(),
+    });
+
+    vec![
+        ("empty", Xfbin::default()),
+        (
+            "single_page",
+            Xfbin {
+                pages: vec![page(fixture_structs(121))],
+                ..Default::default()
+            },
+        ),
+        (
+            "page_per_type",
+            Xfbin {
+                // The morph model refers to chunks that are in other pages
+                pages: fixture_structs(121)
+                    .into_iter()
+                    .map(|s| XfbinPage {
+                        structs: vec![s],
+                        struct_infos: vec![clump_info(), struct_info("binary", NuccChunkType::NuccChunkBinary)],
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        ),
+        (
+            "versions",
+            Xfbin {
+                header_version: 0x63,
+                index_version: 0x63,
+                version: 0x63,
+                pages: vec![page(fixture_structs(0x63)), page(fixture_structs(0x79))],
+                ..Default::default()
+            },
+        ),
+        (
+            "references",
+            Xfbin {
+                pages: vec![referencing_page, page(fixture_structs(121))],
+                ..Default::default()
+            },
+        ),
+    ]
+}
+
+pub fn assert_vec_close(actual: &[f32], expected: &[f32]) {
+    assert!(
+        actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-4),
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
@@ -0,0 +1,662 @@
+//! # Texture decoding
+//! CPU decoding of NUT texture data to RGBA8 and encoding back, for previews and simple edits.
+//!
+//! Block compressed formats are encoded with a fast endpoint fit rather than a full search,
+//! so re-encoded textures are lower quality than ones made with a dedicated tool.
+//!
+use std::io;
+
+use super::error::{Result, XfbinError};
+use super::nucc_chunk::nucc_chunk_texture::{NutPixelFormat, NutTexture};
+
+/// An RGBA8 image, with the face and mipmap level it was decoded from
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub face: usize,
+    pub mipmap: usize,
+
+    pub data: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut buf, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder.write_header()?.write_image_data(&self.data)?;
+
+        Ok(buf)
+    }
+
+    /// Halves the image with a box filter
+    fn downsample(&self) -> Self {
+        // Empty images have nothing to sample
+        if self.width == 0 || self.height == 0 {
+            return Self {
+                mipmap: self.mipmap + 1,
+                ..self.clone()
+            };
+        }
+
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let samples = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+                    let sx = (x * 2 + dx).min(self.width - 1);
+                    let sy = (y * 2 + dy).min(self.height - 1);
+
+                    ((sy * self.width + sx) * 4) as usize
+                });
+
+                for channel in 0..4 {
+                    let sum: u32 = samples.iter().map(|&i| self.data[i + channel] as u32).sum();
+                    data.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+
+        Self {
+            width,
+            height,
+            face: self.face,
+            mipmap: self.mipmap + 1,
+            data,
+        }
+    }
+}
+
+impl NutTexture {
+    /// Decodes every face and mipmap level, each face followed by its mipmaps
+    pub fn decode(&self) -> Result<Vec<RgbaImage>> {
+        let format = self.format()?;
+        let faces = if self.is_cubemap() { 6 } else { 1 };
+
+        let mut images = Vec::new();
+        let mut offset = 0;
+
+        for face in 0..faces {
+            for mipmap in 0..self.mipmap_count.max(1) as usize {
+                let width = (self.width as usize >> mipmap).max(1);
+                let height = (self.height as usize >> mipmap).max(1);
+                let size = format.surface_size(width, height);
+
+                let data = self
+                    .texture_data
+                    .get(offset..offset + size)
+                    .ok_or_else(|| XfbinError::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
+
+                images.push(RgbaImage {
+                    width: width as u32,
+                    height: height as u32,
+                    face,
+                    mipmap,
+                    data: decode_surface(format, width, height, data),
+                });
+
+                offset += size;
+            }
+        }
+
+        Ok(images)
+    }
+
+    /// Exports the first face at full size as a PNG
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let format = self.format()?;
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        let data = self
+            .texture_data
+            .get(..format.surface_size(width, height))
+            .ok_or_else(|| XfbinError::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
+
+        RgbaImage {
+            width: width as u32,
+            height: height as u32,
+            data: decode_surface(format, width, height, data),
+            ..Default::default()
+        }
+        .to_png()
+    }
+
+    /// Creates a texture by encoding `image` to `format`, generating `mipmap_count` levels
+    pub fn from_rgba(image: &RgbaImage, format: NutPixelFormat, mipmap_count: u16) -> Result<Self> {
+        let invalid_size = || XfbinError::InvalidImageSize {
+            width: image.width,
+            height: image.height,
+        };
+
+        // NUT textures store their size in 16 bits
+        let width = u16::try_from(image.width).map_err(|_| invalid_size())?;
+        let height = u16::try_from(image.height).map_err(|_| invalid_size())?;
+
+        if width == 0 || height == 0 {
+            return Err(invalid_size());
+        }
+
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(invalid_size)?;
+
+        if image.data.len() < expected {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mipmap_count = mipmap_count.max(1);
+
+        let mut mipmaps = Vec::new();
+        let mut texture_data = Vec::new();
+        let mut level = image.clone();
+
+        for i in 0..mipmap_count {
+            if i > 0 {
+                level = level.downsample();
+            }
+
+            let data = encode_surface(format, level.width as usize, level.height as usize, &level.data);
+
+            mipmaps.push(data.len() as u32);
+            texture_data.extend(data);
+        }
+
+        // Single mipmap textures have no mipmap size table
+        if mipmap_count == 1 {
+            mipmaps.clear();
+        }
+
+        Ok(Self {
+            mipmap_count,
+            pixel_format: format as u16,
+            width,
+            height,
+            mipmaps,
+            texture_data,
+            ..Default::default()
+        })
+    }
+}
+
+/// Decodes a single surface to RGBA8
+pub fn decode_surface(format: NutPixelFormat, width: usize, height: usize, data: &[u8]) -> Vec<u8> {
+    let mut rgba = vec![0; width * height * 4];
+
+    if format.is_compressed() {
+        let blocks_x = width.div_ceil(4).max(1);
+        let block_size = format.block_size();
+
+        for (i, block) in data.chunks_exact(block_size).enumerate() {
+            let pixels = decode_block(format, block);
+
+            let bx = (i % blocks_x) * 4;
+            let by = (i / blocks_x) * 4;
+
+            for (p, pixel) in pixels.iter().enumerate() {
+                let (x, y) = (bx + p % 4, by + p / 4);
+
+                if x < width && y < height {
+                    let o = (y * width + x) * 4;
+                    rgba[o..o + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+    } else {
+        for (pixel, out) in data.chunks_exact(format.block_size()).zip(rgba.chunks_exact_mut(4)) {
+            out.copy_from_slice(&decode_pixel(format, pixel));
+        }
+    }
+
+    rgba
+}
+
+/// Encodes a single RGBA8 surface
+pub fn encode_surface(format: NutPixelFormat, width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(format.surface_size(width, height));
+
+    if width == 0 || height == 0 {
+        return data;
+    }
+
+    if format.is_compressed() {
+        for by in (0..height).step_by(4) {
+            for bx in (0..width).step_by(4) {
+                // Pixels past the edge repeat the last row or column
+                let pixels: [[u8; 4]; 16] = std::array::from_fn(|p| {
+                    let x = (bx + p % 4).min(width - 1);
+                    let y = (by + p / 4).min(height - 1);
+                    let o = (y * width + x) * 4;
+
+                    [rgba[o], rgba[o + 1], rgba[o + 2], rgba[o + 3]]
+                });
+
+                data.extend(encode_block(format, &pixels));
+            }
+        }
+    } else {
+        for pixel in rgba.chunks_exact(4).take(width * height) {
+            data.extend(encode_pixel(format, [pixel[0], pixel[1], pixel[2], pixel[3]]));
+        }
+    }
+
+    data
+}
+
+fn expand_bits(value: u16, bits: u32) -> u8 {
+    let value = value as u32;
+
+    ((value << (8 - bits)) | (value >> (2 * bits).saturating_sub(8))) as u8
+}
+
+fn quantize(value: u8, bits: u32) -> u16 {
+    ((value as u32 * ((1 << bits) - 1) + 127) / 255) as u16
+}
+
+fn decode_pixel(format: NutPixelFormat, pixel: &[u8]) -> [u8; 4] {
+    let packed = || u16::from_le_bytes([pixel[0], pixel[1]]);
+
+    match format {
+        NutPixelFormat::R8G8B8A8 => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        NutPixelFormat::B8G8R8A8 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+        NutPixelFormat::B5G6R5 => {
+            let [r, g, b] = unpack_565(packed());
+            [r, g, b, 255]
+        }
+        NutPixelFormat::B4G4R4A4 => {
+            let v = packed();
+            [
+                expand_bits((v >> 8) & 0xF, 4),
+                expand_bits((v >> 4) & 0xF, 4),
+                expand_bits(v & 0xF, 4),
+                expand_bits(v >> 12, 4),
+            ]
+        }
+        NutPixelFormat::B5G5R5A1 => {
+            let v = packed();
+            [
+                expand_bits((v >> 10) & 0x1F, 5),
+                expand_bits((v >> 5) & 0x1F, 5),
+                expand_bits(v & 0x1F, 5),
+                if v & 0x8000 != 0 { 255 } else { 0 },
+            ]
+        }
+        _ => unreachable!("{:?} is block compressed", format),
+    }
+}
+
+fn encode_pixel(format: NutPixelFormat, [r, g, b, a]: [u8; 4]) -> Vec<u8> {
+    match format {
+        NutPixelFormat::R8G8B8A8 => vec![r, g, b, a],
+        NutPixelFormat::B8G8R8A8 => vec![b, g, r, a],
+        NutPixelFormat::B5G6R5 => pack_565([r, g, b]).to_le_bytes().to_vec(),
+        NutPixelFormat::B4G4R4A4 => {
+            let v = (quantize(a, 4) << 12) | (quantize(r, 4) << 8) | (quantize(g, 4) << 4) | quantize(b, 4);
+            v.to_le_bytes().to_vec()
+        }
+        NutPixelFormat::B5G5R5A1 => {
+            let v = ((a >= 128) as u16) << 15 | (quantize(r, 5) << 10) | (quantize(g, 5) << 5) | quantize(b, 5);
+            v.to_le_bytes().to_vec()
+        }
+        _ => unreachable!("{:?} is block compressed", format),
+    }
+}
+
+fn unpack_565(v: u16) -> [u8; 3] {
+    [
+        expand_bits(v >> 11, 5),
+        expand_bits((v >> 5) & 0x3F, 6),
+        expand_bits(v & 0x1F, 5),
+    ]
+}
+
+fn pack_565([r, g, b]: [u8; 3]) -> u16 {
+    (quantize(r, 5) << 11) | (quantize(g, 6) << 5) | quantize(b, 5)
+}
+
+fn decode_block(format: NutPixelFormat, block: &[u8]) -> [[u8; 4]; 16] {
+    match format {
+        NutPixelFormat::Dxt1 => decode_color_block(block, true),
+        NutPixelFormat::Dxt3 => {
+            let mut pixels = decode_color_block(&block[8..], false);
+            let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                pixel[3] = expand_bits(((alpha >> (i * 4)) & 0xF) as u16, 4);
+            }
+
+            pixels
+        }
+        NutPixelFormat::Dxt5 => {
+            let mut pixels = decode_color_block(&block[8..], false);
+
+            for (pixel, alpha) in pixels.iter_mut().zip(decode_channel_block(&block[..8])) {
+                pixel[3] = alpha;
+            }
+
+            pixels
+        }
+        NutPixelFormat::Bc4 => decode_channel_block(block).map(|r| [r, r, r, 255]),
+        NutPixelFormat::Bc5 => {
+            let red = decode_channel_block(&block[..8]);
+            let green = decode_channel_block(&block[8..]);
+
+            std::array::from_fn(|i| [red[i], green[i], 0, 255])
+        }
+        _ => unreachable!("{:?} is not block compressed", format),
+    }
+}
+
+fn encode_block(format: NutPixelFormat, pixels: &[[u8; 4]; 16]) -> Vec<u8> {
+    match format {
+        NutPixelFormat::Dxt1 => encode_color_block(pixels, true).to_vec(),
+        NutPixelFormat::Dxt3 => {
+            let alpha = pixels
+                .iter()
+                .enumerate()
+                .fold(0u64, |alpha, (i, p)| alpha | (quantize(p[3], 4) as u64) << (i * 4));
+
+            [alpha.to_le_bytes(), encode_color_block(pixels, false)].concat()
+        }
+        NutPixelFormat::Dxt5 => {
+            let alpha = encode_channel_block(&pixels.map(|p| p[3]));
+            [alpha, encode_color_block(pixels, false)].concat()
+        }
+        NutPixelFormat::Bc4 => encode_channel_block(&pixels.map(|p| p[0])).to_vec(),
+        NutPixelFormat::Bc5 => [
+            encode_channel_block(&pixels.map(|p| p[0])),
+            encode_channel_block(&pixels.map(|p| p[1])),
+        ]
+        .concat(),
+        _ => unreachable!("{:?} is not block compressed", format),
+    }
+}
+
+/// The four colors of a BC1 color block, the last one is transparent in three color mode
+fn color_palette(c0: u16, c1: u16, allow_transparent: bool) -> [[u8; 4]; 4] {
+    let [r0, g0, b0] = unpack_565(c0).map(|c| c as u32);
+    let [r1, g1, b1] = unpack_565(c1).map(|c| c as u32);
+
+    let mix = |a: u32, b: u32, wa: u32, wb: u32| ((a * wa + b * wb) / (wa + wb)) as u8;
+
+    if c0 > c1 || !allow_transparent {
+        [
+            [r0 as u8, g0 as u8, b0 as u8, 255],
+            [r1 as u8, g1 as u8, b1 as u8, 255],
+            [mix(r0, r1, 2, 1), mix(g0, g1, 2, 1), mix(b0, b1, 2, 1), 255],
+            [mix(r0, r1, 1, 2), mix(g0, g1, 1, 2), mix(b0, b1, 1, 2), 255],
+        ]
+    } else {
+        [
+            [r0 as u8, g0 as u8, b0 as u8, 255],
+            [r1 as u8, g1 as u8, b1 as u8, 255],
+            [mix(r0, r1, 1, 1), mix(g0, g1, 1, 1), mix(b0, b1, 1, 1), 255],
+            [0, 0, 0, 0],
+        ]
+    }
+}
+
+fn decode_color_block(block: &[u8], allow_transparent: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let palette = color_palette(c0, c1, allow_transparent);
+
+    std::array::from_fn(|i| palette[((indices >> (i * 2)) & 0x3) as usize])
+}
+
+fn encode_color_block(pixels: &[[u8; 4]; 16], allow_transparent: bool) -> [u8; 8] {
+    let transparent = allow_transparent && pixels.iter().any(|p| p[3] < 128);
+
+    let opaque = pixels.iter().filter(|p| !transparent || p[3] >= 128);
+
+    // Use the corners of the bounding box of the colors as the endpoints
+    let (min, max) = opaque.fold(([255u8; 3], [0u8; 3]), |(min, max), p| {
+        (
+            [min[0].min(p[0]), min[1].min(p[1]), min[2].min(p[2])],
+            [max[0].max(p[0]), max[1].max(p[1]), max[2].max(p[2])],
+        )
+    });
+
+    let (mut c0, mut c1) = (pack_565(max), pack_565(min));
+
+    if c0 < c1 {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+
+    // Four color mode needs c0 > c1, and three color mode needs c0 <= c1
+    if transparent {
+        std::mem::swap(&mut c0, &mut c1);
+    } else if c0 == c1 {
+        let [a, b] = c0.to_le_bytes();
+
+        return [a, b, a, b, 0, 0, 0, 0];
+    }
+
+    let palette = color_palette(c0, c1, allow_transparent);
+    let colors = if transparent { 3 } else { 4 };
+
+    let indices = pixels.iter().enumerate().fold(0u32, |indices, (i, p)| {
+        let index = if transparent && p[3] < 128 {
+            3
+        } else {
+            nearest(&palette[..colors], |c| {
+                (0..3).map(|j| (c[j] as i32 - p[j] as i32).pow(2)).sum::<i32>()
+            })
+        };
+
+        indices | (index as u32) << (i * 2)
+    });
+
+    let [a, b] = c0.to_le_bytes();
+    let [c, d] = c1.to_le_bytes();
+    let [e, f, g, h] = indices.to_le_bytes();
+
+    [a, b, c, d, e, f, g, h]
+}
+
+/// The eight values of a BC4 block
+fn channel_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let (a0, a1) = (a0 as u32, a1 as u32);
+    let mix = |wa: u32, wb: u32, d: u32| ((a0 * wa + a1 * wb) / d) as u8;
+
+    if a0 > a1 {
+        [
+            a0 as u8,
+            a1 as u8,
+            mix(6, 1, 7),
+            mix(5, 2, 7),
+            mix(4, 3, 7),
+            mix(3, 4, 7),
+            mix(2, 5, 7),
+            mix(1, 6, 7),
+        ]
+    } else {
+        [
+            a0 as u8,
+            a1 as u8,
+            mix(4, 1, 5),
+            mix(3, 2, 5),
+            mix(2, 3, 5),
+            mix(1, 4, 5),
+            0,
+            255,
+        ]
+    }
+}
+
+fn decode_channel_block(block: &[u8]) -> [u8; 16] {
+    let palette = channel_palette(block[0], block[1]);
+
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+
+    std::array::from_fn(|i| palette[((indices >> (i * 3)) & 0x7) as usize])
+}
+
+fn encode_channel_block(values: &[u8; 16]) -> [u8; 8] {
+    let max = *values.iter().max().unwrap();
+    let min = *values.iter().min().unwrap();
+
+    let palette = channel_palette(max, min);
+
+    let indices = values.iter().enumerate().fold(0u64, |indices, (i, &v)| {
+        let index = if max == min {
+            0
+        } else {
+            nearest(&palette[..], |&p| (p as i32 - v as i32).abs())
+        };
+
+        indices | (index as u64) << (i * 3)
+    });
+
+    let bits = indices.to_le_bytes();
+
+    [max, min, bits[0], bits[1], bits[2], bits[3], bits[4], bits[5]]
+}
+
+fn nearest<T>(palette: &[T], distance: impl Fn(&T) -> i32) -> usize {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, c)| distance(c))
+        .map_or(0, |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(width: u32, height: u32) -> RgbaImage {
+        // Two colors per block that every format can represent exactly
+        let data = (0..width * height)
+            .flat_map(|i| {
+                if (i % width + i / width).is_multiple_of(2) {
+                    [255, 255, 255, 255]
+                } else {
+                    [0, 0, 0, 255]
+                }
+            })
+            .collect();
+
+        RgbaImage {
+            width,
+            height,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn texture_codec_test() -> Result<()> {
+        let formats = [
+            NutPixelFormat::Dxt1,
+            NutPixelFormat::Dxt3,
+            NutPixelFormat::Dxt5,
+            NutPixelFormat::Bc4,
+            NutPixelFormat::Bc5,
+            NutPixelFormat::B5G5R5A1,
+            NutPixelFormat::B4G4R4A4,
+            NutPixelFormat::B5G6R5,
+            NutPixelFormat::R8G8B8A8,
+            NutPixelFormat::B8G8R8A8,
+        ];
+
+        // Not a multiple of the block size, to cover partial blocks
+        let image = test_image(10, 6);
+
+        for format in formats {
+            let texture = NutTexture::from_rgba(&image, format, 3)?;
+
+            assert_eq!(texture.mipmaps.len(), 3);
+            assert_eq!(
+                texture.texture_data.len(),
+                texture.mipmaps.iter().sum::<u32>() as usize,
+                "{:?}",
+                format
+            );
+
+            let images = texture.decode()?;
+            assert_eq!(images.len(), 3);
+            assert_eq!((images[2].width, images[2].height), (2, 1));
+
+            let expected = match format {
+                // Single channel formats only keep red, and green for BC5
+                NutPixelFormat::Bc4 => image.data.chunks(4).flat_map(|p| [p[0], p[0], p[0], 255]).collect(),
+                NutPixelFormat::Bc5 => image.data.chunks(4).flat_map(|p| [p[0], p[1], 0, 255]).collect(),
+                _ => image.data.clone(),
+            };
+
+            assert_eq!(images[0].data, expected, "{:?}", format);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn texture_decode_block_test() -> Result<()> {
+        // Red and blue endpoints with every pixel using the second endpoint
+        let texture = NutTexture {
+            pixel_format: NutPixelFormat::Dxt1 as u16,
+            width: 4,
+            height: 4,
+            texture_data: vec![0x00, 0xF8, 0x1F, 0x00, 0x55, 0x55, 0x55, 0x55],
+            ..Default::default()
+        };
+
+        let images = texture.decode()?;
+        assert!(images[0].data.chunks(4).all(|p| p == [0, 0, 255, 255]));
+
+        // Transparent pixels use the fourth color in three color mode
+        let mut image = test_image(4, 4);
+        image.data[3] = 0;
+
+        let texture = NutTexture::from_rgba(&image, NutPixelFormat::Dxt1, 1)?;
+        let decoded = &texture.decode()?[0];
+
+        assert_eq!(decoded.data[..4], [0, 0, 0, 0]);
+        assert_eq!(decoded.data[4..], image.data[4..]);
+
+        let png = texture.to_png()?;
+        assert_eq!(&png[1..4], b"PNG");
+
+        Ok(())
+    }
+
+    #[test]
+    fn texture_invalid_size_test() {
+        // Sizes that are empty or don't fit in the NUT header are rejected instead of wrapping
+        for (width, height) in [(0, 4), (4, 0), (0x10000, 1), (u32::MAX, u32::MAX)] {
+            let image = RgbaImage {
+                width,
+                height,
+                ..Default::default()
+            };
+
+            assert!(matches!(
+                NutTexture::from_rgba(&image, NutPixelFormat::R8G8B8A8, 2),
+                Err(XfbinError::InvalidImageSize { .. })
+            ));
+        }
+
+        let empty = RgbaImage {
+            width: 0,
+            height: 4,
+            ..Default::default()
+        }
+        .downsample();
+
+        assert_eq!((empty.width, empty.height, empty.mipmap), (0, 4, 1));
+        assert!(empty.data.is_empty());
+    }
+}
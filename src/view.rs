@@ -0,0 +1,179 @@
+//! # Borrowed view
+//! A read-only view over an xfbin in memory (e.g. a memory mapped file) where chunk
+//! data is borrowed from the buffer instead of being copied.
+//!
+//! Encrypted xfbins can't be viewed since they have to be decrypted into a new buffer first.
+//!
+use binrw::io::Cursor;
+use std::io;
+use std::mem::size_of;
+
+use super::error::{Result, XfbinError};
+use super::nucc_chunk::{NuccChunk, NuccChunkType};
+use super::reader::{XfbinChunkDescriptor, XfbinReader, CHUNK_HEADER_SIZE};
+use super::xfbin_file::{XfbinHeader, XfbinIndex};
+
+#[derive(Debug, Clone)]
+pub struct XfbinView<'a> {
+    pub header: XfbinHeader,
+    pub index: XfbinIndex,
+
+    chunks: Vec<XfbinChunkView<'a>>,
+}
+
+impl<'a> XfbinView<'a> {
+    pub fn new(buf: &'a [u8]) -> Result<Self> {
+        let (header, index, descriptors) = XfbinReader::new(Cursor::new(buf))?.into_parts();
+
+        let chunks = descriptors
+            .into_iter()
+            .map(|descriptor| {
+                let start = (descriptor.offset + CHUNK_HEADER_SIZE) as usize;
+                let end = start + descriptor.size as usize;
+
+                let data = buf
+                    .get(start..end)
+                    .ok_or_else(|| XfbinError::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
+
+                Ok(XfbinChunkView { descriptor, data })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { header, index, chunks })
+    }
+
+    /// Every chunk in the file, including the null and page chunks
+    pub fn chunks(&self) -> &[XfbinChunkView<'a>] {
+        &self.chunks
+    }
+
+    /// The chunks that can be decoded into structs
+    pub fn struct_chunks(&self) -> impl Iterator<Item = &XfbinChunkView<'a>> {
+        self.chunks.iter().filter(|chunk| {
+            !matches!(
+                chunk.descriptor.chunk_type(),
+                NuccChunkType::NuccChunkNull | NuccChunkType::NuccChunkPage
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XfbinChunkView<'a> {
+    pub descriptor: XfbinChunkDescriptor,
+
+    /// Chunk data borrowed from the viewed buffer
+    pub data: &'a [u8],
+}
+
+impl<'a> XfbinChunkView<'a> {
+    /// Returns the payload of a `nuccChunkBinary` without copying it
+    pub fn binary(&self) -> Result<Option<NuccChunkBinaryRef<'a>>> {
+        if self.descriptor.chunk_type() != NuccChunkType::NuccChunkBinary {
+            return Ok(None);
+        }
+
+        let size_bytes = self
+            .data
+            .get(..size_of::<u32>())
+            .ok_or_else(|| XfbinError::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
+
+        let size = u32::from_be_bytes(size_bytes.try_into().unwrap()) as usize;
+
+        let data = self.data[size_of::<u32>()..]
+            .get(..size)
+            .ok_or_else(|| XfbinError::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
+
+        Ok(Some(NuccChunkBinaryRef {
+            version: self.descriptor.version,
+            data,
+        }))
+    }
+
+    /// Returns the data of a chunk with a type that isn't supported, which is kept as is by `NuccChunkUnknown`
+    pub fn unknown(&self) -> Option<NuccChunkUnknownRef<'a>> {
+        (self.descriptor.chunk_type() == NuccChunkType::NuccChunkUnknown).then_some(NuccChunkUnknownRef {
+            version: self.descriptor.version,
+            data: self.data,
+        })
+    }
+
+    /// Decodes the chunk into an owned one
+    pub fn unpack(&self) -> Result<Box<dyn NuccChunk>> {
+        NuccChunkType::read_data(
+            self.data,
+            &self.descriptor.struct_info.chunk_type,
+            self.descriptor.version,
+        )
+        .map_err(|err| XfbinError::ChunkParse {
+            chunk_name: self.descriptor.struct_info.chunk_name.clone(),
+            chunk_type: self.descriptor.struct_info.chunk_type.clone(),
+            offset: self.descriptor.offset,
+            source: Box::new(err),
+        })
+    }
+}
+
+/// Borrowed counterpart of `NuccChunkBinary`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NuccChunkBinaryRef<'a> {
+    pub version: u16,
+    pub data: &'a [u8],
+}
+
+/// Borrowed counterpart of `NuccChunkUnknown`, the chunk type is in the chunk's descriptor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NuccChunkUnknownRef<'a> {
+    pub version: u16,
+    pub data: &'a [u8],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use crate::write_xfbin_buf;
+    use crate::xfbin::Xfbin;
+
+    #[test]
+    fn xfbin_view_test() -> Result<()> {
+        let buf = write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        })?;
+
+        let view = XfbinView::new(&buf)?;
+
+        let names = view
+            .struct_chunks()
+            .map(|chunk| chunk.descriptor.struct_info.chunk_name.as_str())
+            .collect::<Vec<_>>();
+        let expected_names = fixture_structs(121)
+            .iter()
+            .map(|s| s.struct_info().chunk_name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, expected_names);
+
+        let binary = view
+            .struct_chunks()
+            .find_map(|chunk| chunk.binary().transpose())
+            .unwrap()?;
+        assert_eq!(binary.data, bytes(37));
+
+        // The payloads point into the viewed buffer
+        assert!(buf.as_ptr_range().contains(&binary.data.as_ptr()));
+
+        let unknown = view.struct_chunks().find_map(|chunk| chunk.unknown()).unwrap();
+        assert_eq!(unknown.data, bytes(20));
+        assert!(buf.as_ptr_range().contains(&unknown.data.as_ptr()));
+
+        let camera = view
+            .struct_chunks()
+            .find(|chunk| chunk.descriptor.chunk_type() == NuccChunkType::NuccChunkCamera)
+            .unwrap()
+            .unpack()?;
+        assert_eq!(camera.chunk_type(), NuccChunkType::NuccChunkCamera);
+
+        Ok(())
+    }
+}
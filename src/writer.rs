@@ -0,0 +1,368 @@
+//! # Streaming writer
+//! Writes an xfbin one page at a time so that large archives don't have to be built in memory.
+//!
+//! The index comes before the chunks but can only be built once every page has been written,
+//! so the chunks are written to a spool as their pages are finished, and `finish` writes the
+//! header and index followed by the spooled chunks. The spool is a temporary file by default, and
+//! can be any other stream with `XfbinWriter::with_spool`.
+//!
+use binrw::{io::Cursor, BinWrite};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{env, process};
+
+use super::cipher::XfbinCipher;
+use super::error::{Result, XfbinError};
+use super::nucc::{NuccStruct, NuccStructReference};
+use super::xfbin::{WriteOptions, Xfbin, XfbinIndexBuilder, XfbinPage};
+use super::xfbin_file::XfbinHeader;
+
+/// Size of the blocks that spooled chunks are copied to the writer in
+const COPY_BLOCK_SIZE: usize = 0x10000;
+
+/// Temporary file that chunks are spooled to, which is removed when it's dropped
+#[derive(Debug)]
+pub struct SpoolFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl SpoolFile {
+    /// Creates an empty spool file in the temporary directory
+    pub fn new() -> Result<Self> {
+        static SPOOL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "xfbin-spool-{}-{}.tmp",
+            process::id(),
+            SPOOL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+
+        Ok(Self { file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Read for SpoolFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for SpoolFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for SpoolFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        // Nothing can be done if the file is already gone
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct XfbinWriter<W: Write, S: Read + Write + Seek = SpoolFile> {
+    writer: W,
+    spool: S,
+    cipher: Option<Arc<dyn XfbinCipher>>,
+
+    pub header_version: u32,
+    pub index_version: u16,
+
+    /// Chunk version of the null and page chunks
+    pub version: u16,
+    pub encrypted: bool,
+
+    index_builder: XfbinIndexBuilder,
+    page: Option<XfbinPage>,
+}
+
+impl<W: Write> XfbinWriter<W> {
+    /// Creates a writer that spools the chunks to a temporary file
+    pub fn new(writer: W) -> Result<Self> {
+        Self::with_options(writer, &WriteOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: &WriteOptions) -> Result<Self> {
+        Ok(Self::with_spool(writer, SpoolFile::new()?, options))
+    }
+}
+
+impl<W: Write, S: Read + Write + Seek> XfbinWriter<W, S> {
+    /// Creates a writer that keeps the chunks in `spool`, which should be empty, until they're written by `finish`
+    pub fn with_spool(writer: W, spool: S, options: &WriteOptions) -> Self {
+        let Xfbin {
+            header_version,
+            index_version,
+            version,
+            encrypted,
+            ..
+        } = Xfbin::default();
+
+        Self {
+            writer,
+            spool,
+            cipher: options.cipher.clone(),
+            header_version,
+            index_version,
+            version,
+            encrypted,
+            index_builder: XfbinIndexBuilder::new(),
+            page: None,
+        }
+    }
+
+    /// Adds a struct to the current page, starting a new page if needed
+    pub fn add_struct(&mut self, nucc_struct: Box<dyn NuccStruct>) {
+        self.page.get_or_insert_with(XfbinPage::default).structs.push(nucc_struct);
+    }
+
+    /// Adds a reference to the current page, starting a new page if needed
+    pub fn add_reference(&mut self, struct_reference: NuccStructReference) {
+        self.page
+            .get_or_insert_with(XfbinPage::default)
+            .struct_references
+            .push(struct_reference);
+    }
+
+    /// Writes the current page, if there is one
+    ///
+    /// The structs of a page can refer to each other, so a page is only converted to chunks once it's complete
+    pub fn finish_page(&mut self) -> Result<()> {
+        match self.page.take() {
+            Some(page) => self.write_page(page),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the current page, if there is one, followed by `page`
+    pub fn add_page(&mut self, page: XfbinPage) -> Result<()> {
+        self.finish_page()?;
+        self.write_page(page)
+    }
+
+    /// Fails before anything is written if the file is encrypted without a cipher
+    fn check_cipher(&self) -> Result<()> {
+        match (self.encrypted, &self.cipher) {
+            (true, None) => Err(XfbinError::MissingCipher),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_page(&mut self, page: XfbinPage) -> Result<()> {
+        self.check_cipher()?;
+
+        // The chunks of a page are written to the spool at once rather than field by field
+        let mut cursor = Cursor::new(Vec::new());
+
+        for chunk in self.index_builder.pack_page(page, self.version)? {
+            chunk.write_be(&mut cursor)?;
+        }
+
+        Ok(self.spool.write_all(&cursor.into_inner())?)
+    }
+
+    /// Writes the current page, then the header, the index and the spooled chunks, and returns the writer
+    pub fn finish(mut self) -> Result<W> {
+        self.finish_page()?;
+        self.check_cipher()?;
+
+        let header = XfbinHeader {
+            version: self.header_version,
+            encrypted: self.encrypted as u16,
+        };
+
+        let mut index_builder = std::mem::replace(&mut self.index_builder, XfbinIndexBuilder::new());
+        let null_chunk = index_builder.null_chunk(self.version)?;
+        let index = index_builder.finish(self.index_version);
+
+        let mut cursor = Cursor::new(Vec::new());
+        index.write_be(&mut cursor)?;
+        null_chunk.write_be(&mut cursor)?;
+        let index_buf = cursor.into_inner();
+
+        let mut cursor = Cursor::new(Vec::new());
+        header.write_be(&mut cursor)?;
+        self.writer.write_all(&cursor.into_inner())?;
+
+        // Everything after the header is encrypted, starting from offset 0
+        let mut offset = 0;
+        self.write_encrypted(index_buf, &mut offset)?;
+
+        self.spool.seek(SeekFrom::Start(0))?;
+
+        loop {
+            let mut buf = vec![0; COPY_BLOCK_SIZE];
+            let read = self.spool.read(&mut buf)?;
+
+            if read == 0 {
+                break;
+            }
+
+            buf.truncate(read);
+            self.write_encrypted(buf, &mut offset)?;
+        }
+
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    /// Writes `buf` at `offset` past the header, encrypting it if needed
+    fn write_encrypted(&mut self, mut buf: Vec<u8>, offset: &mut u64) -> Result<()> {
+        if let (true, Some(cipher)) = (self.encrypted, &self.cipher) {
+            cipher.encrypt(&mut buf, *offset);
+        }
+
+        self.writer.write_all(&buf)?;
+        *offset += buf.len() as u64;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::XorCipher;
+    use crate::test_util::*;
+    use crate::nucc::NuccBinary;
+    use crate::{read_xfbin_buf, write_xfbin_buf, write_xfbin_buf_with};
+
+    #[test]
+    fn xfbin_writer_test() -> Result<()> {
+        // The fixtures are built twice since writing consumes them
+        for ((name, xfbin), (_, expected)) in fixtures().into_iter().zip(fixtures()) {
+            let expected = write_xfbin_buf(expected)?;
+
+            let mut writer = XfbinWriter::new(Cursor::new(Vec::new()))?;
+            writer.header_version = xfbin.header_version;
+            writer.index_version = xfbin.index_version;
+            writer.version = xfbin.version;
+
+            for page in xfbin.pages {
+                writer.add_page(page)?;
+            }
+
+            assert_eq!(writer.finish()?.into_inner(), expected, "{}", name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn xfbin_writer_structs_test() -> Result<()> {
+        let mut writer = XfbinWriter::new(Cursor::new(Vec::new()))?;
+
+        for nucc_struct in fixture_structs(121) {
+            writer.add_struct(nucc_struct);
+        }
+        writer.finish_page()?;
+
+        writer.add_struct(fixture_structs(121).remove(0));
+
+        let buf = writer.finish()?.into_inner();
+        let xfbin = read_xfbin_buf(buf)?;
+
+        assert_eq!(xfbin.pages.len(), 2);
+        assert_eq!(xfbin.pages[0].structs.len(), fixture_structs(121).len());
+        assert_eq!(
+            xfbin.pages[1].structs[0].downcast_ref::<NuccBinary>().unwrap().data,
+            bytes(37)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn xfbin_writer_spool_test() -> Result<()> {
+        let expected = write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121)), page(fixture_structs(121))],
+            ..Default::default()
+        })?;
+
+        // Nothing is written until the index is known, the chunks only go to the spool
+        let mut output = Vec::new();
+        let mut spool = Cursor::new(Vec::new());
+
+        let mut writer = XfbinWriter::with_spool(&mut output, &mut spool, &WriteOptions::default());
+        writer.add_page(page(fixture_structs(121)))?;
+        writer.add_page(page(fixture_structs(121)))?;
+        writer.finish()?;
+
+        assert_eq!(output, expected);
+        assert!(expected.ends_with(spool.get_ref()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn xfbin_writer_spool_file_test() -> Result<()> {
+        let mut writer = XfbinWriter::new(Vec::new())?;
+        let path = writer.spool.path().to_path_buf();
+
+        // Finished pages are on disk rather than in memory
+        writer.add_page(page(fixture_structs(121)))?;
+        let spooled = fs::read(&path)?;
+
+        assert!(path.starts_with(env::temp_dir()));
+        assert!(!spooled.is_empty());
+
+        let buf = writer.finish()?;
+        assert!(buf.ends_with(&spooled));
+
+        // The spool file is removed once the writer is done with it
+        assert!(!path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn xfbin_writer_encrypted_test() -> Result<()> {
+        let cipher: Arc<dyn XfbinCipher> = Arc::new(XorCipher::new(*b"writer"));
+        let options = WriteOptions {
+            cipher: Some(cipher),
+        };
+
+        let expected = write_xfbin_buf_with(
+            Xfbin {
+                encrypted: true,
+                pages: vec![page(fixture_structs(121))],
+                ..Default::default()
+            },
+            &options,
+        )?;
+
+        let mut writer = XfbinWriter::with_options(Cursor::new(Vec::new()), &options)?;
+        writer.encrypted = true;
+        writer.add_page(page(fixture_structs(121)))?;
+
+        assert_eq!(writer.finish()?.into_inner(), expected);
+
+        Ok(())
+    }
+}
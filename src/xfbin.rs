@@ -1,33 +1,66 @@
 use binrw::NullString;
 use indexmap::IndexMap;
 use itertools::Itertools;
+use std::sync::Arc;
 
+use super::cipher::XfbinCipher;
+use super::error::{Result, XfbinError};
 use super::nucc::*;
 use super::nucc_chunk::*;
 use super::xfbin_file::*;
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Xfbin {
+    /// Version of the `XfbinHeader`
+    pub header_version: u32,
+
+    /// Version of the `XfbinIndex`
+    pub index_version: u16,
+
+    /// Chunk version of the null and page chunks, each struct carries its own chunk version
     pub version: u16,
+
+    /// Whether everything after the header is encrypted when written
+    pub encrypted: bool,
+
     pub pages: Vec<XfbinPage>,
 }
 
+impl Default for Xfbin {
+    fn default() -> Self {
+        Self {
+            header_version: 121,
+            index_version: 121,
+            version: 121,
+            encrypted: false,
+            pages: Vec::new(),
+        }
+    }
+}
+
 impl Xfbin {
+    #[allow(clippy::borrowed_box)]
     pub fn find_nucc_structs(&self, chunk_type: NuccChunkType) -> Vec<&Box<dyn NuccStruct>> {
         self
             .pages
             .iter()
             .flat_map(|page| {
-                page.structs.iter().filter_map(|nucc_struct| {
-                    if nucc_struct.chunk_type() == chunk_type {
-                        Some(nucc_struct)
-                    } else {
-                        None
-                    }
-                })
+                page.structs
+                    .iter()
+                    .filter(|nucc_struct| nucc_struct.chunk_type() == chunk_type)
             })
             .collect()
     }
+
+    /// Appends a page, returning its index
+    pub fn add_page(&mut self, page: XfbinPage) -> usize {
+        self.pages.push(page);
+        self.pages.len() - 1
+    }
+
+    pub fn remove_page(&mut self, page_index: usize) -> Option<XfbinPage> {
+        (page_index < self.pages.len()).then(|| self.pages.remove(page_index))
+    }
 }
 
 #[derive(Debug, Default)]
@@ -38,6 +71,86 @@ pub struct XfbinPage {
 }
 
 impl XfbinPage {
+    fn struct_position(&self, struct_info: &NuccStructInfo) -> Option<usize> {
+        self.structs.iter().position(|nucc_struct| nucc_struct.struct_info() == struct_info)
+    }
+
+    /// Whether a chunk map of the page, or one of its structs, has this struct info
+    pub fn contains_struct_info(&self, struct_info: &NuccStructInfo) -> bool {
+        self.struct_infos.contains(struct_info) || self.struct_position(struct_info).is_some()
+    }
+
+    pub fn get_struct(&self, struct_info: &NuccStructInfo) -> Option<&dyn NuccStruct> {
+        self.struct_position(struct_info).map(|i| self.structs[i].as_ref())
+    }
+
+    /// Adds a struct and its chunk map, failing if the page already has a struct with the same info
+    pub fn add_struct(&mut self, nucc_struct: Box<dyn NuccStruct>) -> Result<()> {
+        let struct_info = nucc_struct.struct_info().clone();
+
+        if self.struct_position(&struct_info).is_some() {
+            return Err(XfbinError::DuplicateStruct(struct_info));
+        }
+
+        if !self.struct_infos.contains(&struct_info) {
+            self.struct_infos.push(struct_info);
+        }
+
+        self.structs.push(nucc_struct);
+
+        Ok(())
+    }
+
+    /// Removes a struct and its chunk map, failing if a reference or another struct of the page still
+    /// points to it
+    pub fn remove_struct(&mut self, struct_info: &NuccStructInfo) -> Result<Box<dyn NuccStruct>> {
+        let position = self
+            .struct_position(struct_info)
+            .ok_or_else(|| XfbinError::UnmappedStructInfo(struct_info.clone()))?;
+
+        if let Some(reference) = self.struct_references.iter().find(|r| &r.struct_info == struct_info) {
+            return Err(XfbinError::DanglingReference(reference.chunk_name.clone()));
+        }
+
+        if let Some(bound_by) = self
+            .structs
+            .iter()
+            .filter(|nucc_struct| nucc_struct.struct_info() != struct_info)
+            .find(|nucc_struct| nucc_struct.bound_struct_infos().contains(&struct_info))
+        {
+            return Err(XfbinError::BoundStruct {
+                struct_info: struct_info.clone(),
+                bound_by: bound_by.struct_info().chunk_name.clone(),
+            });
+        }
+
+        self.struct_infos.retain(|s| s != struct_info);
+
+        Ok(self.structs.remove(position))
+    }
+
+    /// Replaces the struct that has the same info as `nucc_struct`, returning the old one
+    pub fn replace_struct(&mut self, nucc_struct: Box<dyn NuccStruct>) -> Result<Box<dyn NuccStruct>> {
+        let position = self
+            .struct_position(nucc_struct.struct_info())
+            .ok_or_else(|| XfbinError::UnmappedStructInfo(nucc_struct.struct_info().clone()))?;
+
+        Ok(std::mem::replace(&mut self.structs[position], nucc_struct))
+    }
+
+    /// Adds a reference to a chunk of the page, unless the page already has it
+    pub fn add_reference(&mut self, struct_reference: NuccStructReference) -> Result<()> {
+        if !self.contains_struct_info(&struct_reference.struct_info) {
+            return Err(XfbinError::DanglingReference(struct_reference.chunk_name));
+        }
+
+        if !self.struct_references.contains(&struct_reference) {
+            self.struct_references.push(struct_reference);
+        }
+
+        Ok(())
+    }
+
     pub fn has_unknown_chunk(&self) -> bool {
         self.structs.iter().any(|nucc_struct| nucc_struct.chunk_type() == NuccChunkType::NuccChunkUnknown)
     }
@@ -46,6 +159,10 @@ impl XfbinPage {
         self.structs.iter().any(|nucc_struct| nucc_struct.chunk_type() == NuccChunkType::NuccChunkAnm)
     }
 
+    /// Splits the page into its structs and the maps of its chunk maps and references, keeping their order
+    ///
+    /// The chunk maps of a page that was read from a file are kept as they are, so that writing it
+    /// gives the same indices even when other chunks refer to them
     #[allow(clippy::type_complexity)]
     pub fn destructure(self) -> (
         Vec<Box<dyn NuccStruct>>,
@@ -55,27 +172,75 @@ impl XfbinPage {
         let mut struct_infos = IndexMap::new();
         let mut struct_references = IndexMap::new();
 
-        // Clone struct_infos and struct_references before moving them out of self
-        let cloned_struct_infos = self.struct_infos.clone();
-        let cloned_struct_references = self.struct_references.clone();
-
-        if self.has_unknown_chunk() {
-            struct_infos.extend(cloned_struct_infos.into_iter().enumerate().map(|(i, s)| (s, i as u32)));
-            struct_references.extend(cloned_struct_references.into_iter().enumerate().map(|(i, s)| (s, i as u32)));
+        for struct_info in self.struct_infos {
+            insert_struct_info(&mut struct_infos, struct_info);
         }
 
-        if self.has_anm_chunk() {
-            struct_infos.extend(self.struct_infos.into_iter().enumerate().map(|(i, s)| (s, i as u32)));
-            struct_references.extend(self.struct_references.into_iter().enumerate().map(|(i, s)| (s, i as u32)));
+        for struct_reference in self.struct_references {
+            let struct_reference_index = struct_references.len() as u32;
+            struct_references.entry(struct_reference).or_insert(struct_reference_index);
         }
 
         (self.structs, struct_infos, struct_references)
     }
 }
 
-impl From<XfbinFile> for Xfbin {
-    fn from(xfbin: XfbinFile) -> Self {
+/// Options controlling how an `XfbinFile` is converted into an `Xfbin`
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// When false, chunks that fail to parse are kept as `NuccUnknown` instead of failing the whole read
+    pub strict: bool,
+
+    /// Used to decrypt files that have the encrypted flag set
+    pub cipher: Option<Arc<dyn XfbinCipher>>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            strict: true,
+            cipher: None,
+        }
+    }
+}
+
+/// Options controlling how an `Xfbin` is written
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Used to encrypt an `Xfbin` that has the encrypted flag set
+    pub cipher: Option<Arc<dyn XfbinCipher>>,
+}
+
+/// A chunk that failed to parse and was kept as raw bytes in lenient mode
+#[derive(Debug)]
+pub struct ChunkDiagnostic {
+    pub page_index: usize,
+    pub struct_info: NuccStructInfo,
+    pub error: XfbinError,
+}
+
+impl TryFrom<XfbinFile> for Xfbin {
+    type Error = XfbinError;
+
+    fn try_from(xfbin: XfbinFile) -> Result<Self> {
+        Self::from_file(xfbin, &ReadOptions::default()).map(|(xfbin, _)| xfbin)
+    }
+}
+
+impl Xfbin {
+    pub fn from_file(xfbin: XfbinFile, options: &ReadOptions) -> Result<(Self, Vec<ChunkDiagnostic>)> {
+        let header_version = xfbin.header.version;
+        let encrypted = xfbin.header.encrypted != 0;
+        let index_version = xfbin.index.version;
+
+        // The leading null chunk carries the version used by the null and page chunks
+        let version = xfbin
+            .chunks
+            .first()
+            .map_or(Self::default().version, |chunk| chunk.version);
+
         let mut pages = Vec::new();
+        let mut diagnostics = Vec::new();
 
         let mut page = XfbinPage::default();
 
@@ -103,34 +268,52 @@ impl From<XfbinFile> for Xfbin {
         let mut struct_infos_index: usize = 0;
         let mut struct_references_index: usize = 0;
 
-        let struct_infos = Vec::<NuccStructInfo>::from(XfbinChunkMapConverter {
+        let struct_infos = Vec::<NuccStructInfo>::try_from(XfbinChunkMapConverter {
             chunk_maps: xfbin.index.chunk_maps.clone(),
             chunk_names: chunk_names.clone(),
             chunk_types: chunk_types.clone(),
             filepaths: filepaths.clone(),
-        });
+        })?;
 
-        let struct_references = Vec::<NuccStructReference>::from(XfbinChunkReferenceConverter {
+        let struct_references = Vec::<NuccStructReference>::try_from(XfbinChunkReferenceConverter {
             references: xfbin.index.chunk_references,
             chunk_names,
             struct_infos: struct_infos.clone(),
-        });
+        })?;
 
         let struct_infos_mapped = xfbin
             .index
             .chunk_map_indices
             .iter()
-            .map(|&i| struct_infos[i as usize].clone())
-            .collect::<Vec<NuccStructInfo>>();
+            .map(|&i| {
+                struct_infos
+                    .get(i as usize)
+                    .cloned()
+                    .ok_or_else(|| XfbinError::out_of_range("chunk map", i as usize, struct_infos.len()))
+            })
+            .collect::<Result<Vec<NuccStructInfo>>>()?;
 
         for chunk in xfbin.chunks {
-            let NuccStructInfo {
-                chunk_name,
-                chunk_type,
-                filepath,
-            } = &struct_infos_mapped[struct_infos_index + chunk.chunk_map_index as usize].clone();
-
-            let parsed = chunk.unpack(chunk_type);
+            let mapped_index = struct_infos_index + chunk.chunk_map_index as usize;
+
+            let struct_info = struct_infos_mapped
+                .get(mapped_index)
+                .cloned()
+                .ok_or_else(|| {
+                    XfbinError::out_of_range("chunk map index", mapped_index, struct_infos_mapped.len())
+                })?;
+
+            // Page chunks delimit the pages, so they can never fall back to raw bytes
+            let lenient = !options.strict && struct_info.chunk_type != NuccChunkType::NuccChunkPage.to_string();
+
+            let parsed = match chunk.unpack(&struct_info) {
+                Ok(parsed) => parsed,
+                Err(error) if lenient => {
+                    page.structs.push(fall_back(&chunk, struct_info, pages.len(), error, &mut diagnostics));
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
 
             match parsed.chunk_type() {
                 NuccChunkType::NuccChunkNull => continue,
@@ -139,16 +322,33 @@ impl From<XfbinFile> for Xfbin {
                         version: _,
                         map_index_count: struct_infos_count,
                         reference_count: struct_references_count,
-                    } = parsed.downcast::<NuccChunkPage>().map(|c| *c).ok().unwrap();
+                    } = parsed
+                        .downcast::<NuccChunkPage>()
+                        .map(|c| *c)
+                        .map_err(|c| XfbinError::downcast_mismatch::<NuccChunkPage>(c.chunk_type()))?;
 
                     let struct_infos_count = struct_infos_count as usize;
                     let struct_references_count = struct_references_count as usize;
 
                     page.struct_infos = struct_infos_mapped
-                        [struct_infos_index..(struct_infos_index + struct_infos_count)]
+                        .get(struct_infos_index..(struct_infos_index + struct_infos_count))
+                        .ok_or_else(|| {
+                            XfbinError::out_of_range(
+                                "chunk map index",
+                                struct_infos_index + struct_infos_count,
+                                struct_infos_mapped.len(),
+                            )
+                        })?
                         .to_vec();
-                    page.struct_references = struct_references[struct_references_index
-                        ..(struct_references_index + struct_references_count)]
+                    page.struct_references = struct_references
+                        .get(struct_references_index..(struct_references_index + struct_references_count))
+                        .ok_or_else(|| {
+                            XfbinError::out_of_range(
+                                "chunk reference",
+                                struct_references_index + struct_references_count,
+                                struct_references.len(),
+                            )
+                        })?
                         .to_vec();
 
                     pages.push(page);
@@ -162,152 +362,246 @@ impl From<XfbinFile> for Xfbin {
                 _ => (),
             }
 
-            let mut parsed_struct = Box::<dyn NuccStruct>::from(NuccStructConverter {
+            let converted = Box::<dyn NuccStruct>::try_from(NuccStructConverter {
                 nucc_chunk: parsed,
-                struct_infos: struct_infos_mapped.clone(),
-                struct_references: struct_references.clone(),
+                struct_infos: struct_infos_mapped.get(struct_infos_index..).unwrap_or_default(),
+                struct_references: struct_references.get(struct_references_index..).unwrap_or_default(),
             });
 
-            let struct_info = parsed_struct.struct_info_mut();
-            struct_info.chunk_name = chunk_name.clone();
-            struct_info.filepath = filepath.clone();
-            struct_info.chunk_type = chunk_type.clone();
+            match converted {
+                Ok(mut parsed_struct) => {
+                    *parsed_struct.struct_info_mut() = struct_info;
 
-            page.structs.push(parsed_struct);
+                    page.structs.push(parsed_struct);
+                }
+                Err(error) if lenient => {
+                    page.structs.push(fall_back(&chunk, struct_info, pages.len(), error, &mut diagnostics));
+                }
+                Err(error) => return Err(error),
+            }
         }
 
-        Self {
-            version: 121,
-            pages,
-        }
+        Ok((
+            Self {
+                header_version,
+                index_version,
+                version,
+                encrypted,
+                pages,
+            },
+            diagnostics,
+        ))
     }
 }
 
+/// Keeps a chunk that could not be parsed or converted as raw bytes, recording why
+fn fall_back(
+    chunk: &XfbinChunk,
+    struct_info: NuccStructInfo,
+    page_index: usize,
+    error: XfbinError,
+    diagnostics: &mut Vec<ChunkDiagnostic>,
+) -> Box<dyn NuccStruct> {
+    diagnostics.push(ChunkDiagnostic {
+        page_index,
+        struct_info: struct_info.clone(),
+        error,
+    });
+
+    Box::new(NuccUnknown {
+        chunk_type: struct_info.chunk_type.clone(),
+        struct_info,
+        version: chunk.version,
+        data: chunk.data.clone(),
+    })
+}
+
+/// Inserts a struct info into a chunk map if it is not there yet, returning its index
+fn insert_struct_info(struct_infos: &mut IndexMap<NuccStructInfo, u32>, struct_info: NuccStructInfo) -> u32 {
+    let struct_info_index = struct_infos.len() as u32;
+
+    *struct_infos.entry(struct_info).or_insert(struct_info_index)
+}
+
 fn repack_struct(
     boxed: Box<dyn NuccChunk>,
     struct_info: NuccStructInfo,
     page_struct_infos: &mut IndexMap<NuccStructInfo, u32>,
-) -> XfbinChunk {
-    let struct_info_index = page_struct_infos.len() as u32;
-
-    let chunk_map_index = *page_struct_infos
-        .entry(struct_info)
-        .or_insert(struct_info_index);
+) -> Result<XfbinChunk> {
+    let chunk_map_index = insert_struct_info(page_struct_infos, struct_info);
 
-    let mut chunk = XfbinChunk::repack(boxed);
+    let mut chunk = XfbinChunk::repack(boxed)?;
     chunk.chunk_map_index = chunk_map_index;
 
-    chunk
+    Ok(chunk)
 }
 
-impl From<Xfbin> for XfbinFile {
-    fn from(xfbin: Xfbin) -> Self {
-        let mut header = XfbinHeader::default();
-        header.version = 121 as u32;
+impl TryFrom<Xfbin> for XfbinFile {
+    type Error = XfbinError;
 
-        let mut index = XfbinIndex::default();
-        index.version = 121 as u16;
+    fn try_from(xfbin: Xfbin) -> Result<Self> {
+        let header = XfbinHeader {
+            version: xfbin.header_version,
+            encrypted: xfbin.encrypted as u16,
+        };
 
-        let mut min_page_size = 0;
+        let mut index_builder = XfbinIndexBuilder::new();
 
-        let mut chunks = vec![];
+        let mut chunks = vec![index_builder.null_chunk(xfbin.version)?];
 
-        let mut struct_infos_map = IndexMap::<NuccStructInfo, u32>::new();
+        for page in xfbin.pages {
+            chunks.extend(index_builder.pack_page(page, xfbin.version)?);
+        }
 
-        let mut chunk_map_indices = vec![];
-        let mut struct_references_vec = vec![];
+        Ok(Self {
+            header,
+            index: index_builder.finish(xfbin.index_version),
+            chunks,
+        })
+    }
+}
 
-        let null_chunk = repack_struct(
-            Box::new(NuccChunkNull(xfbin.version)),
-            NuccChunkNull::default_chunk_info(),
-            &mut struct_infos_map,
-        );
+/// Builds the chunk maps of an `XfbinIndex` one page at a time
+#[derive(Debug)]
+pub(crate) struct XfbinIndexBuilder {
+    struct_infos_map: IndexMap<NuccStructInfo, u32>,
+    chunk_map_indices: Vec<u32>,
+    struct_references: Vec<NuccStructReference>,
+    min_page_size: usize,
+}
 
-        chunks.push(null_chunk);
+impl XfbinIndexBuilder {
+    pub(crate) fn new() -> Self {
+        let mut struct_infos_map = IndexMap::new();
 
-        for page in xfbin.pages {
-            let (page_structs, mut page_struct_infos, page_struct_references) = page.destructure();
+        // The leading null chunk always uses the first chunk map
+        insert_struct_info(&mut struct_infos_map, NuccChunkNull::default_chunk_info());
 
-            let null_chunk = repack_struct(
-                Box::new(NuccChunkNull(xfbin.version)),
-                NuccChunkNull::default_chunk_info(),
-                &mut page_struct_infos,
-            );
-            chunks.push(null_chunk);
+        Self {
+            struct_infos_map,
+            chunk_map_indices: Vec::new(),
+            struct_references: Vec::new(),
+            min_page_size: 0,
+        }
+    }
+
+    /// The null chunk that comes before the first page
+    pub(crate) fn null_chunk(&mut self, version: u16) -> Result<XfbinChunk> {
+        repack_struct(
+            Box::new(NuccChunkNull(version)),
+            NuccChunkNull::default_chunk_info(),
+            &mut self.struct_infos_map,
+        )
+    }
 
-            for nucc_struct in page_structs {
-                let struct_info = nucc_struct.struct_info().clone();
+    /// Converts a page to its chunks, starting with a null chunk and ending with the page chunk
+    pub(crate) fn pack_page(&mut self, mut page: XfbinPage, version: u16) -> Result<Vec<XfbinChunk>> {
+        let mut chunks = vec![];
+
+        // The chunk maps of a page that was read from a file are kept as they are, including any
+        // duplicates, so only the maps that are missing get added
+        let mut page_chunk_maps = std::mem::take(&mut page.struct_infos);
+        let (page_structs, _, page_struct_references) = page.destructure();
 
-                let boxed = Box::<dyn NuccChunk>::from(NuccChunkConverter {
-                    nucc_struct,
-                    struct_info_map: page_struct_infos.clone(),
-                    struct_reference_map: page_struct_references.clone(),
-                });
+        // Every page's chunk maps start with the null chunk and end with the page and index chunks
+        if page_chunk_maps.first() != Some(&NuccChunkNull::default_chunk_info()) {
+            page_chunk_maps.insert(0, NuccChunkNull::default_chunk_info());
+        }
 
-                chunks.push(repack_struct(boxed, struct_info, &mut page_struct_infos));
+        // Chunks that the structs bind by index need a chunk map too, even if they aren't in the page
+        let missing_struct_infos = page_structs
+            .iter()
+            .map(|nucc_struct| nucc_struct.struct_info().clone())
+            .chain(
+                page_structs
+                    .iter()
+                    .flat_map(|nucc_struct| nucc_struct.bound_struct_infos())
+                    .cloned(),
+            )
+            .chain(page_struct_references.keys().map(|r| r.struct_info.clone()))
+            .chain([NuccChunkPage::default_chunk_info(), NuccChunkIndex::default_chunk_info()])
+            .collect::<Vec<_>>();
+
+        for struct_info in missing_struct_infos {
+            if !page_chunk_maps.contains(&struct_info) {
+                page_chunk_maps.push(struct_info);
             }
+        }
 
-            // Add nuccChunkPage map
-            repack_struct(
-                Box::new(NuccChunkPage::default()),
-                NuccChunkIndex::default_chunk_info(),
-                &mut page_struct_infos,
-            );
+        // Pages have at least 4 chunk maps, padded with the null chunk
+        while page_chunk_maps.len() < 4 {
+            page_chunk_maps.push(NuccChunkNull::default_chunk_info());
+        }
 
-            // Add nuccChunkIndex map
-            repack_struct(
-                Box::new(NuccChunkIndex),
-                NuccChunkIndex::default_chunk_info(),
-                &mut page_struct_infos,
-            );
+        // Chunks refer to the first of their chunk maps
+        let mut page_struct_infos = IndexMap::<NuccStructInfo, u32>::new();
 
-            let mut map_index_count = page_struct_infos.len() as u32;
+        for (index, struct_info) in page_chunk_maps.iter().enumerate() {
+            page_struct_infos.entry(struct_info.clone()).or_insert(index as u32);
+        }
 
-            if map_index_count < 4 {
-                map_index_count = (map_index_count + 3) & !3;
-            }
-            
-
-            // Create final nuccChunkPage
-            let page_chunk = repack_struct(
-                Box::new(NuccChunkPage {
-                    version: xfbin.version,
-                    map_index_count,
-                    reference_count: page_struct_references.len() as u32,
-                }),
-                NuccChunkPage::default_chunk_info(),
-                &mut page_struct_infos,
-            );
+        let null_chunk = repack_struct(
+            Box::new(NuccChunkNull(version)),
+            NuccChunkNull::default_chunk_info(),
+            &mut page_struct_infos,
+        )?;
+        chunks.push(null_chunk);
+
+        for nucc_struct in page_structs {
+            let struct_info = nucc_struct.struct_info().clone();
+
+            let boxed = Box::<dyn NuccChunk>::try_from(NuccChunkConverter {
+                nucc_struct,
+                struct_info_map: page_struct_infos.clone(),
+                struct_reference_map: page_struct_references.clone(),
+            })?;
+
+            chunks.push(repack_struct(boxed, struct_info, &mut page_struct_infos)?);
+        }
 
-            chunks.push(page_chunk);
+        // Create final nuccChunkPage
+        let page_chunk = repack_struct(
+            Box::new(NuccChunkPage {
+                version,
+                map_index_count: page_chunk_maps.len() as u32,
+                reference_count: page_struct_references.len() as u32,
+            }),
+            NuccChunkPage::default_chunk_info(),
+            &mut page_struct_infos,
+        )?;
+
+        chunks.push(page_chunk);
+
+        // The largest number of chunk maps in a page, not counting the null, page and index maps
+        self.min_page_size = self.min_page_size.max(page_chunk_maps.len() - 3);
+
+        for struct_info in page_chunk_maps {
+            self.chunk_map_indices
+                .push(insert_struct_info(&mut self.struct_infos_map, struct_info));
+        }
 
-            for struct_info in page_struct_infos
-                .clone()
+        self.struct_references.extend(
+            page_struct_references
                 .into_iter()
                 .sorted_by_key(|(_, v)| *v)
-                .map(|(k, _)| k)
-            {
-                let struct_info_index = struct_infos_map.len() as u32;
-                chunk_map_indices.push(
-                    *struct_infos_map
-                        .entry(struct_info)
-                        .or_insert(struct_info_index),
-                );
-            }
-
-            struct_references_vec.extend(
-                page_struct_references
-                    .into_iter()
-                    .sorted_by_key(|(_, v)| *v)
-                    .map(|(k, _)| k),
-            );
+                .map(|(k, _)| k),
+        );
 
-            // Get the smallest ie the minimum page_struct_infos length for structs that are not Null or Page
-            if min_page_size == 0 || page_struct_infos.len() > min_page_size {
-                min_page_size = page_struct_infos.len() - 3;
-            }
-            
+        Ok(chunks)
+    }
 
+    pub(crate) fn finish(self, version: u16) -> XfbinIndex {
+        let Self {
+            mut struct_infos_map,
+            mut chunk_map_indices,
+            struct_references,
+            min_page_size,
+        } = self;
+
+        // The leading null chunk maps to the first entry of the first page, so a file
+        // without pages still needs that entry
+        if chunk_map_indices.is_empty() {
+            chunk_map_indices.push(0);
         }
 
         let mut chunk_type_map = IndexMap::new();
@@ -342,7 +636,7 @@ impl From<Xfbin> for XfbinFile {
             })
             .collect::<Vec<XfbinChunkMap>>();
 
-        let chunk_references = struct_references_vec
+        let chunk_references = struct_references
             .iter()
             .map(|struct_reference| {
                 let mut chunk_name_index = chunk_name_map.len() as u32;
@@ -379,26 +673,282 @@ impl From<Xfbin> for XfbinFile {
             .sorted_by_key(|(_, v)| *v)
             .map(|(k, _)| NullString::from(k))
             .collect_vec();
-        
 
-        
+        XfbinIndex {
+            min_page_size: min_page_size as u32,
+            version,
+            chunk_types,
+            filepaths,
+            chunk_names,
+            chunk_maps,
+            chunk_references,
+            chunk_map_indices,
+        }
+    }
+}
 
-        index.min_page_size = min_page_size as u32;
-        index.chunk_types = chunk_types;
-        index.filepaths = filepaths;
-        index.chunk_names = chunk_names;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nucc_chunk::nucc_chunk_anm::AnmClump;
+    use crate::test_util::*;
+    use crate::{read_xfbin_buf, read_xfbin_buf_with, write_xfbin_buf};
+
+    #[test]
+    fn read_xfbin_lenient_test() -> Result<()> {
+        let anm_struct_info = NuccStructInfo {
+            chunk_name: String::from("broken"),
+            filepath: String::from("c\\broken.max"),
+            chunk_type: NuccChunkType::NuccChunkAnm.to_string(),
+        };
 
-        index.chunk_maps = chunk_maps;
+        // An ANM chunk whose payload is too short to parse
+        let broken = NuccUnknown {
+            struct_info: anm_struct_info.clone(),
+            version: 121,
+            data: vec![0xFF; 6],
+            chunk_type: anm_struct_info.chunk_type.clone(),
+        };
 
-        index.chunk_references = chunk_references;
-        index.chunk_map_indices = chunk_map_indices;
+        let mut page = XfbinPage::default();
+        page.structs.push(Box::new(broken));
+        page.struct_infos.push(NuccChunkNull::default_chunk_info());
+        page.struct_infos.push(anm_struct_info.clone());
 
-        let xfbin_file = Self {
-            header,
-            index,
-            chunks,
+        let xfbin = Xfbin {
+            pages: vec![page],
+            ..Default::default()
         };
 
-        xfbin_file
+        let buf = write_xfbin_buf(xfbin)?;
+
+        assert!(matches!(
+            read_xfbin_buf(buf.clone()),
+            Err(XfbinError::ChunkParse { .. })
+        ));
+
+        let (xfbin, diagnostics) = read_xfbin_buf_with(
+            buf.clone(),
+            &ReadOptions {
+                strict: false,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].page_index, 0);
+        assert_eq!(diagnostics[0].struct_info, anm_struct_info);
+
+        let unknown = xfbin.pages[0].structs[0]
+            .downcast_ref::<NuccUnknown>()
+            .unwrap();
+        assert_eq!(unknown.chunk_type, anm_struct_info.chunk_type);
+        assert_eq!(unknown.data, vec![0xFF; 6]);
+
+        assert_eq!(write_xfbin_buf(xfbin)?, buf);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_xfbin_lenient_conversion_test() -> Result<()> {
+        let anm_struct_info = struct_info("dangling", NuccChunkType::NuccChunkAnm);
+
+        // An ANM chunk that parses, but whose clump points past the page's chunk maps
+        let data = NuccChunkType::write_data(
+            Box::new(NuccChunkAnm {
+                version: 121,
+                clumps: vec![AnmClump {
+                    clump_index: 7,
+                    bone_material_indices: Vec::new(),
+                    model_indices: Vec::new(),
+                }],
+                ..Default::default()
+            }),
+            121,
+        )?;
+
+        let mut page = XfbinPage::default();
+        page.structs.push(Box::new(NuccUnknown {
+            struct_info: anm_struct_info.clone(),
+            version: 121,
+            data: data.clone(),
+            chunk_type: anm_struct_info.chunk_type.clone(),
+        }));
+        page.struct_infos.push(NuccChunkNull::default_chunk_info());
+        page.struct_infos.push(anm_struct_info.clone());
+
+        let buf = write_xfbin_buf(Xfbin {
+            pages: vec![page],
+            ..Default::default()
+        })?;
+
+        assert!(matches!(
+            read_xfbin_buf(buf.clone()),
+            Err(XfbinError::IndexOutOfRange { .. })
+        ));
+
+        let (xfbin, diagnostics) = read_xfbin_buf_with(
+            buf.clone(),
+            &ReadOptions {
+                strict: false,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].struct_info, anm_struct_info);
+        assert!(matches!(diagnostics[0].error, XfbinError::IndexOutOfRange { .. }));
+
+        let unknown = xfbin.pages[0].structs[0]
+            .downcast_ref::<NuccUnknown>()
+            .unwrap();
+        assert_eq!(unknown.struct_info, anm_struct_info);
+        assert_eq!(unknown.data, data);
+
+        assert_eq!(write_xfbin_buf(xfbin)?, buf);
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_editing_test() -> Result<()> {
+        let mut xfbin = read_xfbin_buf(write_xfbin_buf(Xfbin {
+            pages: vec![page(fixture_structs(121))],
+            ..Default::default()
+        })?)?;
+
+        let binary_info = struct_info("binary", NuccChunkType::NuccChunkBinary);
+        let new_info = struct_info("new", NuccChunkType::NuccChunkBinary);
+        let new_binary = |len| -> Box<dyn NuccStruct> {
+            Box::new(NuccBinary {
+                struct_info: new_info.clone(),
+                version: 121,
+                data: bytes(len),
+            })
+        };
+
+        let page = &mut xfbin.pages[0];
+        let struct_count = page.structs.len();
+        let struct_info_count = page.struct_infos.len();
+
+        page.add_struct(new_binary(4))?;
+        assert!(matches!(page.add_struct(new_binary(4)), Err(XfbinError::DuplicateStruct(_))));
+        assert_eq!(page.structs.len(), struct_count + 1);
+        assert_eq!(page.struct_infos.len(), struct_info_count + 1);
+
+        let old = page.replace_struct(new_binary(8))?;
+        assert_eq!(old.downcast_ref::<NuccBinary>().unwrap().data.len(), 4);
+
+        let reference = NuccStructReference {
+            chunk_name: String::from("new_ref"),
+            struct_info: new_info.clone(),
+        };
+
+        page.add_reference(reference.clone())?;
+        page.add_reference(reference)?;
+        assert_eq!(page.struct_references.len(), 1);
+
+        let dangling = NuccStructReference {
+            chunk_name: String::from("dangling"),
+            struct_info: struct_info("other", NuccChunkType::NuccChunkBinary),
+        };
+        assert!(matches!(page.add_reference(dangling), Err(XfbinError::DanglingReference(_))));
+
+        // Referenced structs can't be removed
+        assert!(matches!(page.remove_struct(&new_info), Err(XfbinError::DanglingReference(_))));
+
+        // Neither can structs that the anm's clump still binds by chunk map index
+        assert!(matches!(
+            page.remove_struct(&binary_info),
+            Err(XfbinError::BoundStruct { struct_info, bound_by }) if struct_info == binary_info && bound_by == "anm"
+        ));
+        assert!(page.contains_struct_info(&binary_info));
+
+        let camera_info = struct_info("camera", NuccChunkType::NuccChunkCamera);
+        page.remove_struct(&camera_info)?;
+        assert!(!page.contains_struct_info(&camera_info));
+        assert!(page.get_struct(&camera_info).is_none());
+
+        let page_index = xfbin.add_page(XfbinPage::default());
+        assert_eq!(page_index, 1);
+        assert!(xfbin.remove_page(page_index).is_some());
+        assert!(xfbin.remove_page(page_index).is_none());
+
+        let xfbin = read_xfbin_buf(write_xfbin_buf(xfbin)?)?;
+        assert!(xfbin.pages[0].contains_struct_info(&binary_info));
+        assert!(!xfbin.pages[0].contains_struct_info(&camera_info));
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_struct_bound_chunks_test() -> Result<()> {
+        // A page built only with add_struct, where the anm binds a clump that isn't one of its structs
+        let mut page = XfbinPage::default();
+
+        for nucc_struct in fixture_structs(121).into_iter().take(2) {
+            page.add_struct(nucc_struct)?;
+        }
+
+        assert!(!page.contains_struct_info(&clump_info()));
+
+        let xfbin = read_xfbin_buf(write_xfbin_buf(Xfbin {
+            pages: vec![page],
+            ..Default::default()
+        })?)?;
+
+        let expected = fixture_structs(121)[1].downcast_ref::<NuccAnm>().unwrap().clumps.clone();
+        let anm = xfbin.get::<NuccAnm>("anm").unwrap();
+
+        assert_eq!(anm.clumps, expected);
+        assert!(xfbin.pages[0].contains_struct_info(&clump_info()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn destructure_keeps_order_test() -> Result<()> {
+        for nucc_struct in fixture_structs(121) {
+            let own_info = nucc_struct.struct_info().clone();
+            let name = own_info.chunk_name.clone();
+
+            // Chunk maps in an order the writer wouldn't pick on its own, with the page and index chunks first
+            let struct_infos = vec![
+                NuccChunkNull::default_chunk_info(),
+                NuccChunkPage::default_chunk_info(),
+                NuccChunkIndex::default_chunk_info(),
+                struct_info("binary", NuccChunkType::NuccChunkBinary),
+                own_info.clone(),
+                struct_info("unused", NuccChunkType::NuccChunkBinary),
+                clump_info(),
+            ];
+
+            let struct_references = vec![NuccStructReference {
+                chunk_name: String::from("clump_ref"),
+                struct_info: clump_info(),
+            }];
+
+            let buf = write_xfbin_buf(Xfbin {
+                pages: vec![XfbinPage {
+                    structs: vec![nucc_struct],
+                    struct_infos: struct_infos.clone(),
+                    struct_references: struct_references.clone(),
+                }],
+                ..Default::default()
+            })?;
+
+            let xfbin = read_xfbin_buf(buf.clone())?;
+            let page = &xfbin.pages[0];
+
+            // Entries that were already there aren't added again, and duplicates are kept as they are
+            assert_eq!(page.struct_infos, struct_infos, "{}", name);
+            assert_eq!(page.struct_references, struct_references, "{}", name);
+            assert_eq!(page.structs[0].struct_info(), &own_info, "{}", name);
+
+            assert_eq!(write_xfbin_buf(xfbin)?, buf, "{}", name);
+        }
+
+        Ok(())
     }
 }
@@ -1,8 +1,14 @@
-use binrw::{binrw, until_eof, NullString};
+use binrw::{binrw, until_eof, BinReaderExt, BinResult, Endian, NullString, ReadOptions};
+use std::io::{Read, Seek};
 use std::mem::size_of;
 
+use super::error::{Result, XfbinError};
+use super::nucc::NuccStructInfo;
 use super::nucc_chunk::{NuccChunk, NuccChunkType};
 
+/// Size of `XfbinHeader` in bytes, everything after it is encrypted in encrypted files
+pub const XFBIN_HEADER_SIZE: usize = 0x10;
+
 #[binrw]
 #[derive(Debug, Clone, Default)]
 pub struct XfbinFile {
@@ -13,6 +19,26 @@ pub struct XfbinFile {
     pub chunks: Vec<XfbinChunk>,
 }
 
+impl XfbinFile {
+    /// Reads an xfbin file section by section so that failures can be attributed
+    /// to the header, the index or a specific chunk
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let header = XfbinHeader::read_checked(reader)?;
+
+        let index = reader
+            .read_be::<XfbinIndex>()
+            .map_err(XfbinError::TruncatedIndex)?;
+
+        let chunks = until_eof(reader, &ReadOptions::new(Endian::Big), ())?;
+
+        Ok(Self {
+            header,
+            index,
+            chunks,
+        })
+    }
+}
+
 #[binrw]
 #[brw(magic = b"NUCC")]
 #[derive(Debug, Clone, Default)]
@@ -22,6 +48,16 @@ pub struct XfbinHeader {
     pub encrypted: u16,
 }
 
+impl XfbinHeader {
+    /// Reads only the header, which is never encrypted
+    pub fn read_checked<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        reader.read_be::<XfbinHeader>().map_err(|err| match err {
+            binrw::Error::BadMagic { pos, .. } => XfbinError::BadMagic { pos },
+            err => err.into(),
+        })
+    }
+}
+
 #[binrw]
 #[derive(Debug, Clone, Default)]
 pub struct XfbinIndex {
@@ -92,8 +128,9 @@ impl XfbinIndex {
 
         let string_sizes = chunk_types_size + filepaths_size + chunk_names_size;
 
-        0x28 + string_sizes + (4 - (string_sizes % 4)) // Add the header size, size of the strings buffer, and the aligned size of the strings
+        0x28 + string_sizes + ((4 - (string_sizes % 4)) % 4) // Add the header size, size of the strings buffer, and the alignment of the strings
         + (self.chunk_maps.len() as u32 * size_of::<XfbinChunkMap>() as u32)
+        + (self.chunk_references.len() as u32 * size_of::<XfbinChunkReference>() as u32)
         + (self.chunk_map_indices.len() as u32 * size_of::<u32>() as u32)
     }
 }
@@ -116,6 +153,11 @@ pub struct XfbinChunkMap {
 #[binrw]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct XfbinChunk {
+    /// Offset of the chunk in the file it was read from, used for error reporting
+    #[bw(ignore)]
+    #[br(parse_with = stream_position)]
+    pub offset: u64,
+
     #[bw(calc = data.len() as u32)]
     pub size: u32,
 
@@ -128,18 +170,30 @@ pub struct XfbinChunk {
     pub data: Vec<u8>,
 }
 
+fn stream_position<R: Read + Seek>(reader: &mut R, _: &ReadOptions, _: ()) -> BinResult<u64> {
+    Ok(reader.stream_position()?)
+}
+
 impl XfbinChunk {
-    pub fn unpack(self, chunk_type: &str) -> Box<dyn NuccChunk> {
-        NuccChunkType::read_data(self.data, chunk_type, self.version)
-            .map(|(_, chunk)| chunk)
-            .unwrap()
+    pub fn unpack(&self, struct_info: &NuccStructInfo) -> Result<Box<dyn NuccChunk>> {
+        let offset = self.offset;
+
+        NuccChunkType::read_data(&self.data, &struct_info.chunk_type, self.version)
+            .map_err(|err| XfbinError::ChunkParse {
+                chunk_name: struct_info.chunk_name.clone(),
+                chunk_type: struct_info.chunk_type.clone(),
+                offset,
+                source: Box::new(err),
+            })
     }
 
-    pub fn repack(boxed: Box<dyn NuccChunk>) -> Self {
-        let mut result = Self::default();
-        result.version = 121;
-        result.data = NuccChunkType::write_data(boxed, result.version).unwrap();
+    pub fn repack(boxed: Box<dyn NuccChunk>) -> Result<Self> {
+        let version = boxed.version();
 
-        result
+        Ok(Self {
+            version,
+            data: NuccChunkType::write_data(boxed, version)?,
+            ..Default::default()
+        })
     }
 }